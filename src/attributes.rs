@@ -0,0 +1,700 @@
+/* Parse, merge, and generate RISC-V .riscv.attributes sections
+ *
+ * Object files describe the ISA and ABI details their code relies on in a
+ * .riscv.attributes section. The section starts with a format version byte, 'A',
+ * followed by subsections, each with a 32-bit length and a vendor name. Within
+ * the "riscv" subsection are sub-subsections, each with a tag byte and a 32-bit
+ * length, and Tag_File (1) holds attributes that apply to the whole object file.
+ * Each attribute is a ULEB128 tag followed by a ULEB128 value, if the tag is even,
+ * or a NUL-terminated string, if the tag is odd.
+ *
+ * The ISA string, eg: rv64i2p1_m2p0_a2p1_c2p0_zicsr2p0, lists the base ISA and
+ * its extensions, each optionally followed by a version, eg: 2p1 for version 2.1
+ *
+ * See https://github.com/riscv-non-isa/riscv-elf-psabi-doc
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See LICENSE for usage and copying.
+ */
+
+use super::manifest::FileIdentifier;
+
+use std::collections::BTreeMap;
+
+/* the name of the section holding the attributes, and its ELF section type */
+pub const SECTION_NAME: &str = ".riscv.attributes";
+pub const SHT_RISCV_ATTRIBUTES: u32 = 0x70000003;
+pub const PT_RISCV_ATTRIBUTES: u32 = 0x70000003;
+
+const FORMAT_VERSION: u8 = b'A';
+const VENDOR_NAME: &str = "riscv";
+const TAG_FILE: u8 = 1;
+
+/* attribute tags */
+const TAG_STACK_ALIGN: u64 = 4;
+const TAG_ARCH: u64 = 5;
+const TAG_UNALIGNED_ACCESS: u64 = 6;
+const TAG_PRIV_SPEC: u64 = 8;
+const TAG_PRIV_SPEC_MINOR: u64 = 10;
+const TAG_PRIV_SPEC_REVISION: u64 = 12;
+
+/* the canonical order of single-letter extensions in an ISA string */
+const CANONICAL_ORDER: &str = "iemafdqlcbkjtpvh";
+
+/* extensions that imply others, eg: toolchains list zmmul alongside m, as m includes its multiply
+   instructions. g is expanded as it's parsed, and the compressed extension's floating-point
+   implications depend on the ISA, see Isa::with_implied() */
+const IMPLIED_EXTENSIONS: [(&str, &[&str]); 28] =
+[
+    ("m",       &[ "zmmul" ]),
+    ("a",       &[ "zaamo", "zalrsc" ]),
+    ("f",       &[ "zicsr" ]),
+    ("d",       &[ "f" ]),
+    ("q",       &[ "d" ]),
+    ("c",       &[ "zca" ]),
+    ("b",       &[ "zba", "zbb", "zbs" ]),
+    ("v",       &[ "zve64d", "zvl128b" ]),
+    ("zcb",     &[ "zca" ]),
+    ("zcd",     &[ "zca", "d" ]),
+    ("zcf",     &[ "zca", "f" ]),
+    ("zcmp",    &[ "zca" ]),
+    ("zcmt",    &[ "zca", "zicsr" ]),
+    ("zfa",     &[ "f" ]),
+    ("zfh",     &[ "zfhmin" ]),
+    ("zfhmin",  &[ "f" ]),
+    ("zicntr",  &[ "zicsr" ]),
+    ("zihpm",   &[ "zicsr" ]),
+    ("zk",      &[ "zkn", "zkr", "zkt" ]),
+    ("zkn",     &[ "zbkb", "zbkc", "zbkx", "zkne", "zknd", "zknh" ]),
+    ("zks",     &[ "zbkb", "zbkc", "zbkx", "zksed", "zksh" ]),
+    ("zve32f",  &[ "zve32x", "f" ]),
+    ("zve32x",  &[ "zvl32b", "zicsr" ]),
+    ("zve64d",  &[ "zve64f", "d" ]),
+    ("zve64f",  &[ "zve64x", "zve32f" ]),
+    ("zve64x",  &[ "zve32x", "zvl64b" ]),
+    ("zvl128b", &[ "zvl64b" ]),
+    ("zvl64b",  &[ "zvl32b" ])
+];
+
+/* describe a base ISA and its extensions, along with their versions, if known */
+#[derive(Clone, PartialEq)]
+pub struct Isa
+{
+    xlen: u32,
+    extensions: BTreeMap<String, Option<(u64, u64)>>
+}
+
+impl Isa
+{
+    /* parse an ISA string, eg: rv64imac_zicsr or rv64i2p1_m2p0 */
+    pub fn parse(text: &str) -> Result<Isa, String>
+    {
+        let lower = text.to_ascii_lowercase();
+        let rest = match lower.strip_prefix("rv")
+        {
+            Some(rest) => rest,
+            None => return Err(format!("ISA string '{}' doesn't start with rv", text))
+        };
+
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let xlen = match &rest[..digits]
+        {
+            "32" => 32,
+            "64" => 64,
+            "128" => 128,
+            other => return Err(format!("ISA string '{}' has unrecognized base width '{}'", text, other))
+        };
+
+        let mut isa = Isa { xlen, extensions: BTreeMap::new() };
+        for component in rest[digits..].split('_').filter(|component| !component.is_empty())
+        {
+            match component.chars().next()
+            {
+                /* multi-letter extensions, eg: zicsr2p0, are separated by underscores */
+                Some('z') | Some('s') | Some('x') =>
+                {
+                    let (name, version) = split_version(component);
+                    isa.add(name, version);
+                },
+
+                /* single-letter extensions can run together, each optionally followed by a version */
+                _ =>
+                {
+                    let mut chars = component.chars().peekable();
+                    while let Some(letter) = chars.next()
+                    {
+                        if !letter.is_ascii_alphabetic()
+                        {
+                            return Err(format!("ISA string '{}' has unexpected '{}'", text, letter));
+                        }
+
+                        /* gather up any version, eg: 2p0 */
+                        let mut version = String::new();
+                        while let Some(&next) = chars.peek()
+                        {
+                            let separator = next == 'p' && !version.is_empty() && !version.contains('p');
+                            if !next.is_ascii_digit() && !separator
+                            {
+                                break;
+                            }
+                            version.push(next);
+                            chars.next();
+                        }
+
+                        let version = parse_version(&version);
+                        match letter
+                        {
+                            /* g is shorthand for imafd_zicsr_zifencei */
+                            'g' => for name in [ "i", "m", "a", "f", "d", "zicsr", "zifencei" ]
+                            {
+                                isa.add(name, None);
+                            },
+                            _ => isa.add(&letter.to_string(), version)
+                        }
+                    }
+                }
+            }
+        }
+
+        match (isa.extensions.contains_key("i"), isa.extensions.contains_key("e"))
+        {
+            (false, false) => Err(format!("ISA string '{}' doesn't have a base integer ISA (i or e)", text)),
+            _ => Ok(isa)
+        }
+    }
+
+    pub fn get_xlen(&self) -> u32 { self.xlen }
+
+    /* add an extension, keeping the newest version if it's already present */
+    fn add(&mut self, name: &str, version: Option<(u64, u64)>)
+    {
+        let existing = self.extensions.entry(String::from(name)).or_insert(version);
+        if version > *existing
+        {
+            *existing = version;
+        }
+    }
+
+    /* add all of the given ISA's extensions to this one */
+    pub fn union(&mut self, other: &Isa)
+    {
+        for (name, version) in &other.extensions
+        {
+            self.add(name, *version);
+        }
+    }
+
+    /* return true if this ISA includes the given extension */
+    pub fn has(&self, name: &str) -> bool { self.extensions.contains_key(name) }
+
+    /* return the extensions in this ISA that are missing from the given ISA, in canonical order.
+       extensions implied by the given ISA's, eg: zmmul by m, aren't missing */
+    pub fn missing_from(&self, other: &Isa) -> Vec<String>
+    {
+        let available = other.with_implied();
+        self.canonical_order().into_iter()
+            .filter(|name| !available.has(name))
+            .cloned()
+            .collect()
+    }
+
+    /* return a copy of this ISA with every extension implied by its extensions added */
+    fn with_implied(&self) -> Isa
+    {
+        let mut isa = self.clone();
+        loop
+        {
+            let mut implied: Vec<&str> = IMPLIED_EXTENSIONS.iter()
+                .filter(|(name, _)| isa.has(name))
+                .flat_map(|(_, implied)| implied.iter().copied())
+                .collect();
+
+            /* the compressed extension includes compressed floating-point loads and stores when the
+               matching floating-point extension is present, though only RV32 has the single-precision ones */
+            if isa.has("c") && isa.has("d")
+            {
+                implied.push("zcd");
+            }
+            if isa.has("c") && isa.has("f") && isa.xlen == 32
+            {
+                implied.push("zcf");
+            }
+
+            implied.retain(|name| !isa.has(name));
+            if implied.is_empty()
+            {
+                return isa;
+            }
+            for name in implied
+            {
+                isa.add(name, None);
+            }
+        }
+    }
+
+    /* list the extensions in canonical order: single letters in the order given by the spec,
+       then multi-letter extensions, which are already sorted alphabetically */
+    fn canonical_order(&self) -> Vec<&String>
+    {
+        let mut single: Vec<&String> = self.extensions.keys().filter(|name| name.len() == 1).collect();
+        single.sort_by_key(|name| CANONICAL_ORDER.find(name.as_str()).unwrap_or(CANONICAL_ORDER.len()));
+
+        let multi = self.extensions.keys().filter(|name| name.len() > 1);
+        single.into_iter().chain(multi).collect()
+    }
+}
+
+impl std::fmt::Display for Isa
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        /* separate extensions with underscores unless they're unversioned single letters, eg: rv64imac_zicsr */
+        let mut text = format!("rv{}", self.xlen);
+        let mut separate = false;
+        for (idx, name) in self.canonical_order().into_iter().enumerate()
+        {
+            let version = self.extensions[name];
+            if idx > 0 && (separate || name.len() > 1 || version.is_some())
+            {
+                text.push('_');
+            }
+            text.push_str(name);
+            if let Some((major, minor)) = version
+            {
+                text.push_str(&format!("{}p{}", major, minor));
+            }
+            separate = name.len() > 1 || version.is_some();
+        }
+        write!(f, "{}", text)
+    }
+}
+
+/* split a trailing version, eg: 2p0, from a multi-letter extension name */
+fn split_version(component: &str) -> (&str, Option<(u64, u64)>)
+{
+    let bytes = component.as_bytes();
+    let mut split = bytes.len();
+    while split > 0 && bytes[split - 1].is_ascii_digit()
+    {
+        split -= 1;
+    }
+    if split > 1 && split < bytes.len() && bytes[split - 1] == b'p' && bytes[split - 2].is_ascii_digit()
+    {
+        split -= 1;
+        while split > 0 && bytes[split - 1].is_ascii_digit()
+        {
+            split -= 1;
+        }
+    }
+
+    (&component[..split], parse_version(&component[split..]))
+}
+
+/* parse a version in the form major[pminor] */
+fn parse_version(version: &str) -> Option<(u64, u64)>
+{
+    let mut parts = version.splitn(2, 'p');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |minor| minor.parse().ok())?;
+    Some((major, minor))
+}
+
+/* the attributes of an object file, or the merged attributes of an executable */
+#[derive(Clone)]
+pub struct Attributes
+{
+    arch: Option<Isa>,
+    stack_align: Option<u64>,
+    unaligned_access: Option<u64>,
+    priv_spec: Option<(u64, u64, u64)>
+}
+
+impl Attributes
+{
+    /* parse the contents of an object file's .riscv.attributes section */
+    pub fn parse(data: &[u8], identifier: &FileIdentifier) -> Attributes
+    {
+        let mut attributes = Attributes { arch: None, stack_align: None, unaligned_access: None, priv_spec: None };
+        let mut priv_spec = (None, None, None);
+
+        if data.first() != Some(&FORMAT_VERSION)
+        {
+            malformed(identifier, "unrecognized format version");
+        }
+
+        let mut position = 1;
+        while position < data.len()
+        {
+            /* each subsection has a length, including the length itself, and a vendor name */
+            let length = read_u32(data, position).unwrap_or_else(|| malformed(identifier, "truncated subsection")) as usize;
+            let end = position.checked_add(length).filter(|end| *end <= data.len() && length >= 4).unwrap_or_else(|| malformed(identifier, "bad subsection length"));
+            let vendor_end = data[position + 4..end].iter().position(|b| *b == 0).unwrap_or_else(|| malformed(identifier, "unterminated vendor name")) + position + 4;
+
+            /* skip attributes defined by other vendors */
+            if &data[position + 4..vendor_end] != VENDOR_NAME.as_bytes()
+            {
+                position = end;
+                continue;
+            }
+
+            let mut sub_position = vendor_end + 1;
+            while sub_position < end
+            {
+                let tag = data[sub_position];
+                let sub_length = read_u32(data, sub_position + 1).unwrap_or_else(|| malformed(identifier, "truncated sub-subsection")) as usize;
+                let sub_end = sub_position.checked_add(sub_length).filter(|sub_end| *sub_end <= end && sub_length >= 5).unwrap_or_else(|| malformed(identifier, "bad sub-subsection length"));
+
+                /* only attributes that apply to the whole file are of interest */
+                if tag == TAG_FILE
+                {
+                    let mut cursor = sub_position + 5;
+                    while cursor < sub_end
+                    {
+                        let (attribute, next) = read_uleb128(data, cursor).unwrap_or_else(|| malformed(identifier, "truncated attribute tag"));
+                        cursor = next;
+
+                        if attribute % 2 == 1
+                        {
+                            /* odd tags have string values */
+                            let string_end = data[cursor..sub_end].iter().position(|b| *b == 0).unwrap_or_else(|| malformed(identifier, "unterminated string")) + cursor;
+                            let value = String::from_utf8_lossy(&data[cursor..string_end]);
+                            cursor = string_end + 1;
+
+                            if attribute == TAG_ARCH
+                            {
+                                attributes.arch = match Isa::parse(&value)
+                                {
+                                    Ok(isa) => Some(isa),
+                                    Err(reason) => malformed(identifier, &reason)
+                                };
+                            }
+                        }
+                        else
+                        {
+                            let (value, next) = read_uleb128(data, cursor).unwrap_or_else(|| malformed(identifier, "truncated attribute value"));
+                            cursor = next;
+
+                            match attribute
+                            {
+                                TAG_STACK_ALIGN => attributes.stack_align = Some(value),
+                                TAG_UNALIGNED_ACCESS => attributes.unaligned_access = Some(value),
+                                TAG_PRIV_SPEC => priv_spec.0 = Some(value),
+                                TAG_PRIV_SPEC_MINOR => priv_spec.1 = Some(value),
+                                TAG_PRIV_SPEC_REVISION => priv_spec.2 = Some(value),
+                                _ => () /* ignore attributes we don't understand */
+                            }
+                        }
+                    }
+                }
+
+                sub_position = sub_end;
+            }
+
+            position = end;
+        }
+
+        if let (Some(major), minor, revision) = priv_spec
+        {
+            attributes.priv_spec = Some((major, minor.unwrap_or(0), revision.unwrap_or(0)));
+        }
+
+        attributes
+    }
+
+    pub fn get_arch(&self) -> &Option<Isa> { &self.arch }
+
+    /* generate the contents of a .riscv.attributes section describing these attributes */
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let mut attributes = Vec::new();
+        if let Some(align) = self.stack_align
+        {
+            write_uleb128(&mut attributes, TAG_STACK_ALIGN);
+            write_uleb128(&mut attributes, align);
+        }
+        if let Some(arch) = &self.arch
+        {
+            write_uleb128(&mut attributes, TAG_ARCH);
+            attributes.extend_from_slice(arch.to_string().as_bytes());
+            attributes.push(0);
+        }
+        if let Some(unaligned) = self.unaligned_access
+        {
+            write_uleb128(&mut attributes, TAG_UNALIGNED_ACCESS);
+            write_uleb128(&mut attributes, unaligned);
+        }
+        if let Some((major, minor, revision)) = self.priv_spec
+        {
+            for (tag, value) in [ (TAG_PRIV_SPEC, major), (TAG_PRIV_SPEC_MINOR, minor), (TAG_PRIV_SPEC_REVISION, revision) ]
+            {
+                write_uleb128(&mut attributes, tag);
+                write_uleb128(&mut attributes, value);
+            }
+        }
+
+        /* wrap the attributes in a Tag_File sub-subsection within a riscv subsection */
+        let file_length = 1 + 4 + attributes.len();
+        let subsection_length = 4 + VENDOR_NAME.len() + 1 + file_length;
+
+        let mut data = vec![FORMAT_VERSION];
+        data.extend_from_slice(&(subsection_length as u32).to_le_bytes());
+        data.extend_from_slice(VENDOR_NAME.as_bytes());
+        data.push(0);
+        data.push(TAG_FILE);
+        data.extend_from_slice(&(file_length as u32).to_le_bytes());
+        data.extend_from_slice(&attributes);
+        data
+    }
+}
+
+/* bail out on a badly formed attributes section */
+fn malformed(identifier: &FileIdentifier, reason: &str) -> !
+{
+    fatal_msg!("Malformed {} section in {}: {}", SECTION_NAME, identifier.to_str().unwrap(), reason)
+}
+
+/* merge the attributes of the given object files into one set of attributes for the executable.
+   the ISA strings are combined so that the executable's ISA includes every extension used,
+   at the newest version used. unaligned access is allowed if any object allows it, and the
+   newest privileged spec version is kept. objects must agree on the stack alignment */
+pub fn merge(objects: &[(&FileIdentifier, Attributes)]) -> Option<Attributes>
+{
+    let mut merged = objects.first()?.1.clone();
+    let mut stack_align_from = objects.first()?.0;
+    let mut conflicts = 0;
+
+    for (identifier, attributes) in &objects[1..]
+    {
+        match (&mut merged.arch, &attributes.arch)
+        {
+            (Some(merged_arch), Some(arch)) =>
+            {
+                if merged_arch.xlen != arch.xlen
+                {
+                    eprintln!("Object {} is built for RV{} but the objects before it are built for RV{}",
+                        identifier.to_str().unwrap(), arch.xlen, merged_arch.xlen);
+                    conflicts += 1;
+                }
+                merged_arch.union(arch);
+            },
+            (None, Some(arch)) => merged.arch = Some(arch.clone()),
+            (_, None) => ()
+        }
+
+        match (merged.stack_align, attributes.stack_align)
+        {
+            (Some(merged_align), Some(align)) if merged_align != align =>
+            {
+                eprintln!("Object {} requires {}-byte stack alignment but {} requires {}-byte stack alignment",
+                    identifier.to_str().unwrap(), align, stack_align_from.to_str().unwrap(), merged_align);
+                conflicts += 1;
+            },
+            (None, Some(align)) =>
+            {
+                merged.stack_align = Some(align);
+                stack_align_from = identifier;
+            },
+            _ => ()
+        }
+
+        merged.unaligned_access = match (merged.unaligned_access, attributes.unaligned_access)
+        {
+            (Some(first), Some(second)) => Some(first | second),
+            (first, second) => first.or(second)
+        };
+
+        if attributes.priv_spec > merged.priv_spec
+        {
+            merged.priv_spec = attributes.priv_spec;
+        }
+    }
+
+    if conflicts > 0
+    {
+        fatal_msg!("Found {} conflict(s) between object files' {} sections", conflicts, SECTION_NAME);
+    }
+
+    Some(merged)
+}
+
+fn read_u32(data: &[u8], position: usize) -> Option<u32>
+{
+    let bytes = data.get(position..position + 4)?;
+    Some(u32::from_le_bytes([ bytes[0], bytes[1], bytes[2], bytes[3] ]))
+}
+
+/* read a ULEB128 value, returning it and the position of the byte after it */
+fn read_uleb128(data: &[u8], mut position: usize) -> Option<(u64, usize)>
+{
+    let mut value = 0;
+    let mut shift = 0;
+    loop
+    {
+        let byte = *data.get(position)?;
+        position += 1;
+        if shift < 64
+        {
+            value |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0
+        {
+            return Some((value, position));
+        }
+    }
+}
+
+fn write_uleb128(data: &mut Vec<u8>, mut value: u64)
+{
+    loop
+    {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0
+        {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn identifier() -> FileIdentifier { FileIdentifier::from("test.o") }
+
+    fn attributes(arch: &str, stack_align: Option<u64>, unaligned_access: Option<u64>, priv_spec: Option<(u64, u64, u64)>) -> Attributes
+    {
+        Attributes { arch: Some(Isa::parse(arch).unwrap()), stack_align, unaligned_access, priv_spec }
+    }
+
+    #[test]
+    fn isa_parse_versions()
+    {
+        let isa = Isa::parse("rv64i2p1_m2p0_a2p1_c2p0_zicsr2p0").unwrap();
+        assert_eq!(isa.get_xlen(), 64);
+        assert_eq!(isa.extensions["i"], Some((2, 1)));
+        assert_eq!(isa.extensions["m"], Some((2, 0)));
+        assert_eq!(isa.extensions["zicsr"], Some((2, 0)));
+        assert_eq!(isa.to_string(), "rv64i2p1_m2p0_a2p1_c2p0_zicsr2p0");
+
+        /* a major version on its own has a minor version of zero, and single letters can run together */
+        let isa = Isa::parse("RV32I2M2p1").unwrap();
+        assert_eq!(isa.get_xlen(), 32);
+        assert_eq!(isa.extensions["i"], Some((2, 0)));
+        assert_eq!(isa.extensions["m"], Some((2, 1)));
+
+        /* the newest version of a repeated extension is kept */
+        let isa = Isa::parse("rv64i2p0_i2p1").unwrap();
+        assert_eq!(isa.extensions["i"], Some((2, 1)));
+    }
+
+    #[test]
+    fn isa_parse_multi_letter_extensions()
+    {
+        let isa = Isa::parse("rv64imac_zifencei_zicsr_xfoo").unwrap();
+        assert!(isa.has("zicsr") && isa.has("zifencei") && isa.has("xfoo"));
+        assert_eq!(isa.extensions["zicsr"], None);
+        assert_eq!(isa.to_string(), "rv64imac_xfoo_zicsr_zifencei");
+
+        /* g stands for the general-purpose extensions */
+        assert_eq!(Isa::parse("rv64gc").unwrap().to_string(), "rv64imafdc_zicsr_zifencei");
+    }
+
+    #[test]
+    fn isa_parse_rejects_bad_input()
+    {
+        assert!(Isa::parse("x86_64").is_err());
+        assert!(Isa::parse("rv48i").is_err());
+        assert!(Isa::parse("rvi").is_err());
+        assert!(Isa::parse("rv64mac").is_err());
+        assert!(Isa::parse("rv64i_m!").is_err());
+    }
+
+    #[test]
+    fn missing_from_expands_implied_extensions()
+    {
+        /* extensions toolchains list alongside the ones that include them aren't missing */
+        let object = Isa::parse("rv64i2p1_m2p0_a2p1_c2p0_zicsr2p0_zmmul1p0_zaamo1p0_zalrsc1p0_zca1p0").unwrap();
+        assert!(object.missing_from(&Isa::parse("rv64imac_zicsr").unwrap()).is_empty());
+
+        /* g brings in the floating-point extensions, and c their compressed loads and stores */
+        let object = Isa::parse("rv64i_d_c_zicsr_zifencei_zcd").unwrap();
+        assert!(object.missing_from(&Isa::parse("rv64gc").unwrap()).is_empty());
+
+        /* only RV32 has compressed single-precision loads and stores */
+        let object = Isa::parse("rv32if_zca_zcf").unwrap();
+        assert!(object.missing_from(&Isa::parse("rv32ifc").unwrap()).is_empty());
+        let object = Isa::parse("rv64if_zca_zcf").unwrap();
+        assert_eq!(object.missing_from(&Isa::parse("rv64ifc").unwrap()), vec![ "zcf" ]);
+
+        /* implications follow on from each other: v implies zve64d, which implies d, which implies f */
+        let object = Isa::parse("rv64if_zve32x").unwrap();
+        assert!(object.missing_from(&Isa::parse("rv64iv").unwrap()).is_empty());
+
+        /* extensions that really are missing are still reported, in canonical order */
+        let object = Isa::parse("rv64imafc_zba_zicsr").unwrap();
+        assert_eq!(object.missing_from(&Isa::parse("rv64imc").unwrap()), vec![ "a", "f", "zba", "zicsr" ]);
+    }
+
+    #[test]
+    fn merge_combines_attributes()
+    {
+        let (first, second) = (FileIdentifier::from("first.o"), FileIdentifier::from("second.o"));
+        let merged = merge(&[
+            (&first, attributes("rv64i2p0_m2p0_zicsr2p0", Some(16), Some(0), Some((1, 11, 0)))),
+            (&second, attributes("rv64i2p1_a2p1_c2p0", None, Some(1), Some((1, 12, 0))))
+        ]).unwrap();
+
+        assert_eq!(merged.get_arch().as_ref().unwrap().to_string(), "rv64i2p1_m2p0_a2p1_c2p0_zicsr2p0");
+        assert_eq!(merged.stack_align, Some(16));
+        assert_eq!(merged.unaligned_access, Some(1));
+        assert_eq!(merged.priv_spec, Some((1, 12, 0)));
+
+        assert!(merge(&[]).is_none());
+    }
+
+    #[test]
+    fn to_bytes_round_trip()
+    {
+        let original = attributes("rv32imac_zicsr", Some(16), Some(1), Some((1, 11, 200)));
+        let data = original.to_bytes();
+
+        /* the subsection length covers everything after the format version, and the
+           Tag_File sub-subsection length everything after the vendor name */
+        assert_eq!(data[0], b'A');
+        assert_eq!(read_u32(&data, 1), Some(data.len() as u32 - 1));
+        assert_eq!(&data[5..11], b"riscv\0");
+        assert_eq!(data[11], TAG_FILE);
+        assert_eq!(read_u32(&data, 12), Some(data.len() as u32 - 11));
+
+        /* each attribute starts with its ULEB128 tag: Tag_stack_align first, with its value */
+        assert_eq!(&data[16..18], &[ TAG_STACK_ALIGN as u8, 16 ]);
+        assert_eq!(&data[18..19], &[ TAG_ARCH as u8 ]);
+
+        /* a revision of 200 needs a two-byte ULEB128 value */
+        assert_eq!(&data[data.len() - 3..], &[ TAG_PRIV_SPEC_REVISION as u8, 0xc8, 0x01 ]);
+
+        let parsed = Attributes::parse(&data, &identifier());
+        assert_eq!(parsed.get_arch().as_ref().unwrap().to_string(), "rv32imac_zicsr");
+        assert_eq!(parsed.stack_align, Some(16));
+        assert_eq!(parsed.unaligned_access, Some(1));
+        assert_eq!(parsed.priv_spec, Some((1, 11, 200)));
+    }
+
+    #[test]
+    fn uleb128_encoding()
+    {
+        let mut data = Vec::new();
+        write_uleb128(&mut data, 624485);
+        assert_eq!(data, vec![ 0xe5, 0x8e, 0x26 ]);
+        assert_eq!(read_uleb128(&data, 0), Some((624485, 3)));
+
+        /* a value running off the end of the data is truncated */
+        assert_eq!(read_uleb128(&data[..2], 0), None);
+    }
+}
@@ -6,8 +6,10 @@
  */
 
 use super::context::{Context, Group, StreamItem};
+use super::config::{ OutputFormat, Strip };
 
 /* use a state machine to analyze command line args */
+#[derive(Clone, Copy, PartialEq)]
 enum State
 {
     ExpectingAnything,
@@ -15,6 +17,10 @@ enum State
     ExpectingOutputFile,
     ExpectingConfigFile,
     ExpectingFlavorType,
+    ExpectingOutputFormat,
+    ExpectingDefsym,
+    ExpectingUndefinedSymbol,
+    ExpectingWrappedSymbol,
     WaitingForGroupEnd
 }
 
@@ -25,6 +31,10 @@ pub fn parse_args() -> Context
     let mut state = State::ExpectingAnything;
     let mut group = Group::new();
 
+    /* the state to return to once a switch's argument has been handled, so
+       that switches inside a group don't take us out of the group */
+    let mut resume = State::ExpectingAnything;
+
     /* get the command-line arguments as a list of strings, skipping
     the first argument because it's just the program name */
     let arg_array = std::env::args().collect::<Vec<String>>().split_off(1);
@@ -39,8 +49,13 @@ pub fn parse_args() -> Context
                or include the object file in the processing stream */
             State::ExpectingAnything =>
             {
-                match parse_single_arg(arg)
+                match parse_single_arg(arg, &mut context)
                 {
+                    (true, Some(State::WaitingForGroupEnd)) =>
+                    {
+                        state = State::WaitingForGroupEnd;
+                        resume = State::WaitingForGroupEnd;
+                    },
                     (true, Some(s)) => state = s,
                     (false, None) => context.add_to_stream(StreamItem::File(arg.clone())),
                     (_, _) => ()
@@ -50,7 +65,7 @@ pub fn parse_args() -> Context
             /* if we're in a group, keep adding archives to the group */
             State::WaitingForGroupEnd =>
             {
-                match parse_single_arg(arg)
+                match parse_single_arg(arg, &mut context)
                 {
                     (true, Some(State::ExpectingAnything)) =>
                     {
@@ -58,25 +73,32 @@ pub fn parse_args() -> Context
                            and create a blank group for next time */
                         context.add_to_stream(StreamItem::Group(group.clone()));
                         state = State::ExpectingAnything;
+                        resume = State::ExpectingAnything;
                         group = Group::new();
                     },
+
+                    /* already in a group, so carry on */
+                    (true, Some(State::WaitingForGroupEnd)) => (),
+
+                    /* handle a switch's argument, then come back to the group */
+                    (true, Some(s)) => state = s,
                     (false, None) => group.add(StreamItem::File(arg.clone())),
                     (_, _) => ()
-                }   
+                }
             }
 
             /* the argument is expected to be a search path */
             State::ExpectingSearchPath =>
             {
                 context.add_to_stream(StreamItem::SearchPath(arg.clone()));
-                state = State::ExpectingAnything;
+                state = resume;
             },
 
             /* the argument is expected to be the executable output filename */
             State::ExpectingOutputFile =>
             {
                 context.set_output_file(arg);
-                state = State::ExpectingAnything;
+                state = resume;
             },
 
             /* the argument is expected to be the linker config script filename.
@@ -84,7 +106,35 @@ pub fn parse_args() -> Context
             State::ExpectingConfigFile =>
             {
                 context.parse_config_file(arg);
-                state = State::ExpectingAnything;
+                state = resume;
+            },
+
+            /* the argument is expected to be the output file format */
+            State::ExpectingOutputFormat =>
+            {
+                context.set_output_format(parse_output_format(arg));
+                state = resume;
+            },
+
+            /* the argument is expected to be a symbol definition, eg: name=expression */
+            State::ExpectingDefsym =>
+            {
+                add_defsym(&mut context, arg);
+                state = resume;
+            },
+
+            /* the argument is expected to be the name of a symbol that must be defined */
+            State::ExpectingUndefinedSymbol =>
+            {
+                context.add_undefined(arg);
+                state = resume;
+            },
+
+            /* the argument is expected to be the name of a symbol to wrap */
+            State::ExpectingWrappedSymbol =>
+            {
+                context.add_wrap(arg);
+                state = resume;
             },
 
             State::ExpectingFlavorType =>
@@ -94,17 +144,40 @@ pub fn parse_args() -> Context
                     super::fatal_msg!("{} only supports the 'gnu' interface flavor",
                         env!("CARGO_PKG_NAME"));
                 }
-                state = State::ExpectingAnything;
+                state = resume;
             }
         }
     }
 
+    context.apply_symbol_options();
     context
 }
 
-/* attempt to parse a single argument and return whether or not the arg
-   was successfully parsed, and the new state of the parser */
-fn parse_single_arg(arg: &String) -> (bool, Option<State>)
+/* add a symbol definition of the form name=expression to the context, or bail out if it's malformed */
+fn add_defsym(context: &mut Context, defsym: &str)
+{
+    match defsym.split_once('=')
+    {
+        Some((name, value)) if !name.trim().is_empty() && !value.trim().is_empty() =>
+            context.add_defsym(name.trim(), value.trim()),
+        _ => super::fatal_msg!("Can't parse --defsym {}: expected <symbol>=<expression>", defsym)
+    }
+}
+
+/* convert an output format name into a format, or bail out if it's not recognized */
+fn parse_output_format(name: &str) -> OutputFormat
+{
+    match OutputFormat::from_name(name)
+    {
+        Some(format) => format,
+        None => super::fatal_msg!("Unsupported output format '{}': use elf, binary, ihex, or srec", name)
+    }
+}
+
+/* attempt to parse a single argument, updating the context if it's a switch that
+   takes effect immediately, and return whether or not the arg was successfully
+   parsed, and the new state of the parser */
+fn parse_single_arg(arg: &String, context: &mut Context) -> (bool, Option<State>)
 {
     /* display minimal help and exit */
     if arg == "--help"
@@ -131,6 +204,44 @@ fn parse_single_arg(arg: &String) -> (bool, Option<State>)
     /* next command line argument must be the config filename */
     if arg == "-T" { return (true, Some(State::ExpectingConfigFile)) }
 
+    /* next command line argument must be the output file format, unless it's given as --oformat=<format> */
+    if arg == "--oformat" { return (true, Some(State::ExpectingOutputFormat)) }
+    if let Some(format) = arg.strip_prefix("--oformat=")
+    {
+        context.set_output_format(parse_output_format(format));
+        return (true, None);
+    }
+
+    /* next command line argument must be a symbol definition, unless it's given as --defsym=<name>=<expression> */
+    if arg == "--defsym" { return (true, Some(State::ExpectingDefsym)) }
+    if let Some(defsym) = arg.strip_prefix("--defsym=")
+    {
+        add_defsym(context, defsym);
+        return (true, None);
+    }
+
+    /* next command line argument must be a symbol the object files must define, unless it's given as --undefined=<name> */
+    if arg == "-u" || arg == "--undefined" { return (true, Some(State::ExpectingUndefinedSymbol)) }
+    if let Some(name) = arg.strip_prefix("--undefined=")
+    {
+        context.add_undefined(name);
+        return (true, None);
+    }
+
+    /* next command line argument must be a symbol whose references are redirected to __wrap_<symbol>,
+       unless it's given as --wrap=<symbol> */
+    if arg == "--wrap" { return (true, Some(State::ExpectingWrappedSymbol)) }
+    if let Some(name) = arg.strip_prefix("--wrap=")
+    {
+        context.add_wrap(name);
+        return (true, None);
+    }
+
+    /* leave out symbols and debugging information from the executable */
+    if arg == "-s" || arg == "--strip-all" { context.set_strip(Strip::All); return (true, None) }
+    if arg == "-S" || arg == "--strip-debug" { context.set_strip(Strip::Debug); return (true, None) }
+    if arg == "-X" || arg == "--discard-locals" { context.set_discard_locals(); return (true, None) }
+
     /* next command line argument will be the interface flavor, which must be 'gnu' */
     if arg == "-flavor" { return (true, Some(State::ExpectingFlavorType)) }
 
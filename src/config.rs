@@ -7,20 +7,89 @@
  */
 
 use serde_derive::Deserialize;
-use std::collections::HashMap;
+use std::collections::{ HashMap, BTreeMap };
+use super::expr::Expression;
+use super::attributes::Isa;
 
 #[derive(Clone)]
 #[derive(Deserialize)]
 pub struct Config
 {
     output: Output,
-    section: HashMap<String, Section>
+    section: HashMap<String, Section>,
+    memory: Option<HashMap<String, Region>>,
+    artifact: Option<Vec<Artifact>>,
+    embed: Option<Vec<Embed>>,
+    symbols: Option<BTreeMap<String, SymbolAssignment>>,
+
+    /* symbols given on the command line with -u that must be defined */
+    #[serde(skip)]
+    required: Vec<String>,
+
+    /* symbols given on the command line with --wrap whose references are redirected */
+    #[serde(skip)]
+    wrapped: Vec<String>
 }
 
 impl Config
 {
     pub fn get_sections(&self) -> &HashMap<String, Section> { &self.section }
     pub fn get_output(&self) -> &Output { &self.output }
+
+    /* return the symbols assigned values by the config, if any */
+    pub fn get_symbol_assignments(&self) -> Option<&BTreeMap<String, SymbolAssignment>> { self.symbols.as_ref() }
+
+    /* give a symbol a value, replacing any given by the config file, eg: from --defsym */
+    pub fn assign_symbol(&mut self, name: &str, value: Expression)
+    {
+        self.symbols.get_or_insert_with(BTreeMap::new).insert(String::from(name), SymbolAssignment::Value(value));
+    }
+
+    /* note a symbol that must be defined by the object files, eg: from -u */
+    pub fn require_symbol(&mut self, name: &str)
+    {
+        self.required.push(String::from(name));
+    }
+
+    pub fn get_required_symbols(&self) -> &Vec<String> { &self.required }
+
+    /* note a symbol whose undefined references go to __wrap_<symbol>, eg: from --wrap */
+    pub fn wrap_symbol(&mut self, name: &str)
+    {
+        self.wrapped.push(String::from(name));
+    }
+
+    pub fn get_wrapped_symbols(&self) -> &Vec<String> { &self.wrapped }
+
+    /* return the files to embed in the executable, if any */
+    pub fn get_embeds(&self) -> &[Embed]
+    {
+        match &self.embed
+        {
+            Some(embeds) => embeds.as_slice(),
+            None => &[]
+        }
+    }
+
+    /* return the extra files to generate alongside the executable, if any */
+    pub fn get_artifacts(&self) -> &[Artifact]
+    {
+        match &self.artifact
+        {
+            Some(artifacts) => artifacts.as_slice(),
+            None => &[]
+        }
+    }
+
+    /* look up a memory region by name */
+    pub fn get_region(&self, name: &String) -> Option<&Region>
+    {
+        match &self.memory
+        {
+            Some(regions) => regions.get(name),
+            None => None
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -31,8 +100,74 @@ pub struct Output
     relocatable: bool,
     start_symbol: Option<String>,
     end_symbol: Option<String>,
-    base_phys_addr: Option<u64>,
-    base_virt_addr: Option<u64>
+    base_phys_addr: Option<Expression>,
+    base_virt_addr: Option<Expression>,
+    warn_section_conflicts: Option<bool>,
+    orphans: Option<OrphanPolicy>,
+    relax: Option<bool>,
+    global_pointer: Option<bool>,
+    ignore_empty_object_abi: Option<bool>,
+    isa: Option<String>,
+    format: Option<OutputFormat>,
+    fill: Option<u8>,
+    narrow_srec: Option<bool>,
+    strip: Option<Strip>
+}
+
+/* what to do with orphans: allocatable input sections that aren't matched by any include pattern.
+   they can be placed alongside sections with compatible flags, like ld does, with or without
+   a warning, or treated as an error */
+#[derive(Clone, Copy, PartialEq)]
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanPolicy
+{
+    Place,
+    Warn,
+    Error
+}
+
+/* the file format to generate: an ELF executable, a flat memory image of the
+   loadable sections starting from the lowest load address, as objcopy -O binary does,
+   or an Intel HEX or Motorola S-record image of the loadable sections */
+#[derive(Clone, Copy, PartialEq)]
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat
+{
+    Elf,
+    Binary,
+    Ihex,
+    Srec
+}
+
+impl OutputFormat
+{
+    /* convert a format name given on the command line, eg: --oformat binary, to a format.
+       accept ld's names for RISC-V ELF files as well as our own */
+    pub fn from_name(name: &str) -> Option<OutputFormat>
+    {
+        match name
+        {
+            "elf" | "elf64-littleriscv" | "elf32-littleriscv" => Some(OutputFormat::Elf),
+            "binary" => Some(OutputFormat::Binary),
+            "ihex" => Some(OutputFormat::Ihex),
+            "srec" => Some(OutputFormat::Srec),
+            _ => None
+        }
+    }
+}
+
+/* what to leave out of an ELF executable: nothing, the debugging information, or
+   the debugging information and the symbol table. a stronger level includes the weaker ones */
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strip
+{
+    None,
+    Debug,
+    All
 }
 
 pub enum ExecutablePlacement
@@ -48,6 +183,49 @@ impl Output
     pub fn get_end_symbol(&self) -> &Option<String> { &self.end_symbol }
     pub fn is_relocatable(&self) -> bool { self.relocatable }
 
+    /* input sections go in the first standard section with a matching pattern.
+       if this returns true, warn about sections matched by other standard sections, too */
+    pub fn warn_section_conflicts(&self) -> bool { self.warn_section_conflicts.unwrap_or(false) }
+
+    /* orphan sections are placed without warning unless the config says otherwise */
+    pub fn get_orphan_policy(&self) -> OrphanPolicy { self.orphans.unwrap_or(OrphanPolicy::Place) }
+
+    /* code is relaxed unless the config says otherwise */
+    pub fn relax(&self) -> bool { self.relax.unwrap_or(true) }
+
+    /* __global_pointer$ is defined by the linker, if not by an object file, unless the config says otherwise */
+    pub fn define_global_pointer(&self) -> bool { self.global_pointer.unwrap_or(true) }
+
+    /* objects must all use the same float and RVE ABIs. if this returns true,
+       the ABIs of objects with no code or data are ignored */
+    pub fn ignore_empty_object_abi(&self) -> bool { self.ignore_empty_object_abi.unwrap_or(false) }
+
+    /* generate an ELF executable unless the config says otherwise */
+    pub fn get_format(&self) -> OutputFormat { self.format.unwrap_or(OutputFormat::Elf) }
+
+    /* gaps between sections in flat memory images are filled with zeroes unless the config says otherwise */
+    pub fn get_fill_byte(&self) -> u8 { self.fill.unwrap_or(0) }
+
+    /* S-record images use S3 and S7 records unless the config asks for the narrowest that fit */
+    pub fn narrow_srec(&self) -> bool { self.narrow_srec.unwrap_or(false) }
+
+    /* nothing is stripped from ELF executables unless the config says otherwise */
+    pub fn get_strip(&self) -> Strip { self.strip.unwrap_or(Strip::None) }
+
+    /* return the ISA the executable must run on, eg: rv64imac_zicsr, if one is given */
+    pub fn get_isa(&self) -> Option<Isa>
+    {
+        match &self.isa
+        {
+            Some(isa) => match Isa::parse(isa)
+            {
+                Ok(isa) => Some(isa),
+                Err(reason) => fatal_msg!("Can't use output.isa in configuration file: {}", reason)
+            },
+            None => None
+        }
+    }
+
     pub fn get_placement(&self) -> ExecutablePlacement
     {
         if self.relocatable
@@ -60,8 +238,11 @@ impl Output
                the ELF spec isn't terribly clear?
                if only one is given (physical or virtual) then use that for both base address fields.
                if individual phys and virt addresses are given, use those.
-               use zero as base addresses if none are given rather than error. */
-            match (self.base_phys_addr, self.base_virt_addr)
+               use zero as base addresses if none are given rather than error.
+               addresses can be integers or strings to evaluate, eg "0xffffffff_80000000" */
+            let phys = self.base_phys_addr.as_ref().map(|addr| addr.evaluate("output.base_phys_addr"));
+            let virt = self.base_virt_addr.as_ref().map(|addr| addr.evaluate("output.base_virt_addr"));
+            match (phys, virt)
             {
                 (None,       None)       => ExecutablePlacement::Static(0, 0),
                 (None,       Some(virt)) => ExecutablePlacement::Static(virt, virt),
@@ -72,20 +253,190 @@ impl Output
     }
 }
 
+/* describe an extra file to generate from the same layout as the executable, eg:
+   [[artifact]]
+   file = "kernel.bin"
+   format = "binary"
+
+   or a copy of the executable without symbols or debugging information:
+   [[artifact]]
+   file = "kernel-stripped.elf"
+   strip = "all"
+
+   the format defaults to elf, and the fill byte, S-record width, and strip level to the executable's.
+   the strip level only applies to elf artifacts */
+#[derive(Clone)]
+#[derive(Deserialize)]
+pub struct Artifact
+{
+    file: String,
+    format: Option<OutputFormat>,
+    fill: Option<u8>,
+    narrow_srec: Option<bool>,
+    strip: Option<Strip>
+}
+
+impl Artifact
+{
+    pub fn get_file(&self) -> &String { &self.file }
+    pub fn get_format(&self) -> OutputFormat { self.format.unwrap_or(OutputFormat::Elf) }
+    pub fn get_fill_byte(&self, output: &Output) -> u8 { self.fill.unwrap_or(output.get_fill_byte()) }
+    pub fn narrow_srec(&self, output: &Output) -> bool { self.narrow_srec.unwrap_or(output.narrow_srec()) }
+    pub fn get_strip(&self, executable: Strip) -> Strip { self.strip.unwrap_or(executable) }
+}
+
+/* describe the value given to a symbol in the [symbols] table. this is either an expression, eg:
+   _stack_top = "ADDR(.bss) + SIZEOF(.bss) + 64K"
+
+   or a table with the expression and whether the symbol is only provided if no object defines it, eg:
+   _heap_start = { value = "_stack_top", provide = true }
+
+   expressions can use ADDR(), LOADADDR(), and SIZEOF() for any section, and the values of other symbols */
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum SymbolAssignment
+{
+    Value(Expression),
+    Table
+    {
+        value: Expression,
+        provide: Option<bool>
+    }
+}
+
+impl SymbolAssignment
+{
+    pub fn get_value(&self) -> &Expression
+    {
+        match self
+        {
+            SymbolAssignment::Value(value) => value,
+            SymbolAssignment::Table { value, .. } => value
+        }
+    }
+
+    /* the value replaces any definition in the object files, unless the config says otherwise */
+    pub fn is_provided(&self) -> bool
+    {
+        match self
+        {
+            SymbolAssignment::Value(_) => false,
+            SymbolAssignment::Table { provide, .. } => provide.unwrap_or(false)
+        }
+    }
+}
+
+/* describe a file to include in the executable as-is, eg:
+   [[embed]]
+   file = "guest.bin"
+   section = ".capsules"
+   align = 4096
+
+   files are placed in the named output section, one after the other in the order given,
+   and each is aligned to its alignment, if one is given. the alignment can be an expression */
+#[derive(Clone)]
+#[derive(Deserialize)]
+pub struct Embed
+{
+    file: String,
+    section: String,
+    align: Option<Expression>
+}
+
+impl Embed
+{
+    pub fn get_file(&self) -> &String { &self.file }
+    pub fn get_section(&self) -> &String { &self.section }
+
+    /* return the file's alignment in bytes, which must be a power of two */
+    pub fn get_alignment(&self) -> u64
+    {
+        let alignment = self.align.as_ref().map_or(1, |align| align.evaluate(&format!("embed.align for {}", self.file)));
+        if !alignment.is_power_of_two()
+        {
+            fatal_msg!("Can't use embed.align for {} in configuration file: 0x{:x} isn't a power of two", self.file, alignment);
+        }
+        alignment
+    }
+}
+
 #[derive(Clone)]
 #[derive(Deserialize)]
 pub struct Section
 {
     include: Vec<String>,
+    exclude: Option<Vec<String>>,
     start_symbol: Option<String>,
-    end_symbol: Option<String>
+    end_symbol: Option<String>,
+    region: Option<String>,
+    load_region: Option<String>,
+    load_start_symbol: Option<String>,
+    load_end_symbol: Option<String>,
+    address: Option<Expression>
 }
 
+/* a section runs from its region, and is loaded into its load region, if one is given.
+   eg, initialized data can be stored in ROM and copied to RAM by startup code using
+   the load_start_symbol, load_end_symbol, and start_symbol addresses.
+   a section can also be given a fixed run address, which can be relative to
+   the sections before it, eg: address = "ADDR(.text) + SIZEOF(.text) + 4K"
+
+   include and exclude patterns match input section names, eg: ".text*", or
+   input section names within particular object files, eg: "*rt0.o(.text*)".
+   an input section is included if it matches an include pattern and no exclude pattern */
 impl Section
 {
     pub fn get_sections_to_include(&self) -> &Vec<String> { &self.include }
+    pub fn get_sections_to_exclude(&self) -> &Option<Vec<String>> { &self.exclude }
     pub fn get_start_symbol(&self) -> &Option<String> { &self.start_symbol }
     pub fn get_end_symbol(&self) -> &Option<String> { &self.end_symbol }
+    pub fn get_region(&self) -> &Option<String> { &self.region }
+    pub fn get_load_region(&self) -> &Option<String> { &self.load_region }
+    pub fn get_load_start_symbol(&self) -> &Option<String> { &self.load_start_symbol }
+    pub fn get_load_end_symbol(&self) -> &Option<String> { &self.load_end_symbol }
+    pub fn get_address(&self) -> &Option<Expression> { &self.address }
+}
+
+/* describe a named area of memory, akin to an entry in an ld script's MEMORY command, eg:
+   [memory.rom]
+   origin = "0x2000_0000"
+   length = "64K"
+   attributes = "rx"
+
+   sections assigned to a region are placed one after the other from its origin.
+   the attributes are any combination of r (readable), w (writeable), and x (executable).
+   if no attributes are given, any section can be placed in the region */
+#[derive(Clone)]
+#[derive(Deserialize)]
+pub struct Region
+{
+    origin: Expression,
+    length: Expression,
+    attributes: Option<String>
+}
+
+impl Region
+{
+    pub fn get_origin(&self, name: &String) -> u64 { self.origin.evaluate(&format!("memory.{}.origin", name)) }
+    pub fn get_length(&self, name: &String) -> u64 { self.length.evaluate(&format!("memory.{}.length", name)) }
+
+    /* return true if the region allows the given r, w, or x attribute */
+    pub fn allows(&self, name: &String, attribute: char) -> bool
+    {
+        match &self.attributes
+        {
+            None => true,
+            Some(attributes) =>
+            {
+                if let Some(bad) = attributes.chars().find(|c| !"rwx".contains(*c))
+                {
+                    fatal_msg!("Unrecognized attribute '{}' for memory region {} in configuration file", bad, name);
+                }
+                attributes.contains(attribute)
+            }
+        }
+    }
 }
 
 /* load the given file into memory and parse it, returning a config structure */
@@ -118,7 +469,17 @@ pub fn default_config() -> Config
             end_symbol: None,
             relocatable: true,
             base_phys_addr: None,
-            base_virt_addr: None
+            base_virt_addr: None,
+            warn_section_conflicts: None,
+            orphans: None,
+            relax: None,
+            global_pointer: None,
+            ignore_empty_object_abi: None,
+            isa: None,
+            format: None,
+            fill: None,
+            narrow_srec: None,
+            strip: None
         },
 
         /* default sections */
@@ -130,35 +491,125 @@ pub fn default_config() -> Config
                 ("text", Section
                 {
                     include: vec![ String::from(".entry*"), String::from(".init*"), String::from(".text*") ],
+                    exclude: None,
                     start_symbol: None,
-                    end_symbol: None
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
                 }),
             
                 ("rodata", Section
                 {
                     include: vec![ String::from(".rodata*") ],
+                    exclude: None,
+                    start_symbol: None,
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
+                }),
+            
+                ("tdata", Section
+                {
+                    include: vec![ String::from(".tdata*") ],
+                    exclude: None,
                     start_symbol: None,
-                    end_symbol: None
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
+                }),
+            
+                ("tbss", Section
+                {
+                    include: vec![ String::from(".tbss*") ],
+                    exclude: None,
+                    start_symbol: None,
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
                 }),
             
                 ("data", Section
                 {
                     include: vec![ String::from(".data*") ],
+                    exclude: None,
+                    start_symbol: None,
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
+                }),
+            
+                ("sdata", Section
+                {
+                    include: vec![ String::from(".srodata*"), String::from(".sdata*") ],
+                    exclude: None,
+                    start_symbol: None,
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
+                }),
+            
+                ("sbss", Section
+                {
+                    include: vec![ String::from(".sbss*"), String::from(".scommon*") ],
+                    exclude: None,
                     start_symbol: None,
-                    end_symbol: None
+                    end_symbol: None,
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
                 }),
             
                 ("bss", Section
                 {
                     include: vec![ String::from(".bss*") ],
+                    exclude: None,
                     start_symbol: Some(String::from("__bss_start")),
-                    end_symbol: Some(String::from("__bss_end"))
+                    end_symbol: Some(String::from("__bss_end")),
+                    region: None,
+                    load_region: None,
+                    load_start_symbol: None,
+                    load_end_symbol: None,
+                    address: None
                 })
             ]
             {
                 tbl.insert(String::from(name), section);
             }
             tbl
-        }
+        },
+
+        /* no memory regions by default */
+        memory: None,
+
+        /* generate just the executable by default */
+        artifact: None,
+
+        /* no embedded files by default */
+        embed: None,
+
+        /* no symbols defined by default */
+        symbols: None,
+        required: Vec::new(),
+        wrapped: Vec::new()
     }
 }
\ No newline at end of file
@@ -9,7 +9,8 @@
  */
 
 use super::search::Paths;
-use super::config::{ self, Config };
+use super::config::{ self, Config, OutputFormat, Strip };
+use super::expr::Expression;
 use super::manifest::Manifest;
 
 pub type Filename = String;
@@ -44,6 +45,12 @@ pub struct Context
 {
     output_file: Filename,          /* this can be set at any time */
     input_stream: Vec<StreamItem>,  /* a list of streamed items to process */
+    format: Option<OutputFormat>,   /* output format given on the command line, if any */
+    defsyms: Vec<(String, String)>, /* symbols and their values given with --defsym */
+    undefined: Vec<String>,         /* symbols given with -u that must be defined */
+    wrapped: Vec<String>,           /* symbols given with --wrap */
+    strip: Strip,                   /* what to strip from ELF executables, given with -s or -S */
+    discard_locals: bool,           /* true to leave temporary .L symbols out of the symbol table */
     config: Config
 }
 
@@ -56,7 +63,13 @@ impl Context
             /* the ld-compatible executable filename default is a.out */
             output_file: String::from("a.out"),
             config: config::default_config(),
-            input_stream: Vec::new()
+            input_stream: Vec::new(),
+            format: None,
+            defsyms: Vec::new(),
+            undefined: Vec::new(),
+            wrapped: Vec::new(),
+            strip: Strip::None,
+            discard_locals: false
         }
     }
 
@@ -79,6 +92,72 @@ impl Context
 
     pub fn get_output_file(&self) -> String { self.output_file.clone() }
 
+    pub fn set_output_format(&mut self, format: OutputFormat)
+    {
+        self.format = Some(format);
+    }
+
+    /* the output format given on the command line takes priority over the config file's */
+    pub fn get_output_format(&self) -> OutputFormat
+    {
+        self.format.unwrap_or(self.config.get_output().get_format())
+    }
+
+    /* strip at least the given level from ELF executables, eg: -s strips everything -S does and more */
+    pub fn set_strip(&mut self, strip: Strip)
+    {
+        if strip > self.strip
+        {
+            self.strip = strip;
+        }
+    }
+
+    /* the command line and the config file can each ask for things to be stripped. do whichever strips more */
+    pub fn get_strip(&self) -> Strip
+    {
+        let configured = self.config.get_output().get_strip();
+        if configured > self.strip { configured } else { self.strip }
+    }
+
+    pub fn set_discard_locals(&mut self) { self.discard_locals = true; }
+    pub fn discard_locals(&self) -> bool { self.discard_locals }
+
+    /* note a symbol definition from the command line, eg: --defsym=name=expression */
+    pub fn add_defsym(&mut self, name: &str, value: &str)
+    {
+        self.defsyms.push((String::from(name), String::from(value)));
+    }
+
+    /* note a symbol from the command line that must be defined, eg: -u name */
+    pub fn add_undefined(&mut self, name: &str)
+    {
+        self.undefined.push(String::from(name));
+    }
+
+    /* note a symbol from the command line whose references are redirected, eg: --wrap=name */
+    pub fn add_wrap(&mut self, name: &str)
+    {
+        self.wrapped.push(String::from(name));
+    }
+
+    /* apply the symbol options given on the command line to the config, once all the
+       arguments have been parsed, so they take effect whether they come before or after -T */
+    pub fn apply_symbol_options(&mut self)
+    {
+        for (name, value) in &self.defsyms
+        {
+            self.config.assign_symbol(name, Expression::Text(value.clone()));
+        }
+        for name in &self.undefined
+        {
+            self.config.require_symbol(name);
+        }
+        for name in &self.wrapped
+        {
+            self.config.wrap_symbol(name);
+        }
+    }
+
     /* parse config file and stash contents in this context */
     pub fn parse_config_file(&mut self, path: &String)
     {
@@ -0,0 +1,408 @@
+/* Evaluate numeric expressions in the configuration file
+ *
+ * TOML integers are signed 64-bit values, which can't describe addresses
+ * in the upper half of the 64-bit address space, eg 0xffffffff80000000.
+ * So allow values to be given as strings, too, which are evaluated here.
+ *
+ * Numbers can be written in decimal, hex (0x), octal (0o), or binary (0b),
+ * with optional _ separators and K, M, or G suffixes (multiples of 1024).
+ * They can be combined using + - * / and parentheses, eg: "0x8000_0000 + 2M"
+ *
+ * Where the context allows, expressions can also use the ld-style functions
+ * ADDR(section), LOADADDR(section), and SIZEOF(section), eg: "ADDR(.text) + SIZEOF(.text)",
+ * and the values of symbols, eg: "_stack_top - 4K"
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See LICENSE for usage and copying.
+ */
+
+use std::convert::TryFrom;
+use serde_derive::Deserialize;
+
+/* an expression is either a plain TOML integer or a string to evaluate */
+#[derive(Clone)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Expression
+{
+    Integer(i64),
+    Text(String)
+}
+
+/* look up the values of names used in an expression */
+pub trait Lookup
+{
+    /* return the result of applying the given function to the given name, eg ADDR(.text),
+       or an explanation of why it can't be done */
+    fn function(&self, function: &str, name: &str) -> Result<u64, String>;
+
+    /* return the value of the given symbol, or an explanation of why it can't be found */
+    fn symbol(&self, name: &str) -> Result<u64, String>
+    {
+        Err(format!("Symbol {} can't be used here", name))
+    }
+}
+
+/* lookup for expressions that may only contain numbers */
+struct NoLookup;
+
+impl Lookup for NoLookup
+{
+    fn function(&self, function: &str, _: &str) -> Result<u64, String>
+    {
+        Err(format!("{}() can't be used here", function))
+    }
+}
+
+impl Expression
+{
+    /* evaluate the expression, or bail out with an error message
+       that identifies the expression by the given description */
+    pub fn evaluate(&self, description: &str) -> u64
+    {
+        self.evaluate_with(description, &NoLookup)
+    }
+
+    /* evaluate the expression, using lookup to find the values of any names */
+    pub fn evaluate_with(&self, description: &str, lookup: &dyn Lookup) -> u64
+    {
+        match self
+        {
+            Expression::Integer(value) => match u64::try_from(*value)
+            {
+                Ok(value) => value,
+                Err(_) => fatal_msg!("Negative value {} for {} in configuration file", value, description)
+            },
+            Expression::Text(text) => match evaluate(text, lookup)
+            {
+                Ok(value) => value,
+                Err(reason) => fatal_msg!("Can't evaluate '{}' for {} in configuration file: {}", text, description, reason)
+            }
+        }
+    }
+
+    /* evaluate the expression, using lookup to find the values of any names,
+       or return an explanation of why it can't be evaluated */
+    pub fn try_evaluate_with(&self, lookup: &dyn Lookup) -> Result<u64, String>
+    {
+        match self
+        {
+            Expression::Integer(value) => u64::try_from(*value).map_err(|_| format!("Negative value {}", value)),
+            Expression::Text(text) => evaluate(text, lookup).map_err(|reason| format!("Can't evaluate '{}': {}", text, reason))
+        }
+    }
+}
+
+/* the building blocks of an expression */
+#[derive(Clone, PartialEq, Debug)]
+enum Token
+{
+    Number(u64),
+    Name(String),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    OpenBracket,
+    CloseBracket
+}
+
+/* evaluate the given string as an expression */
+fn evaluate(text: &str, lookup: &dyn Lookup) -> Result<u64, String>
+{
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens: &tokens, position: 0, lookup };
+    let value = parser.sum()?;
+
+    match parser.peek()
+    {
+        None => Ok(value),
+        Some(token) => Err(format!("Unexpected {:?}", token))
+    }
+}
+
+/* break a string down into tokens */
+fn tokenize(text: &str) -> Result<Vec<Token>, String>
+{
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next()
+    {
+        match c
+        {
+            ' ' | '\t' => (),
+            '+' => tokens.push(Token::Add),
+            '-' => tokens.push(Token::Subtract),
+            '*' => tokens.push(Token::Multiply),
+            '/' => tokens.push(Token::Divide),
+            '(' => tokens.push(Token::OpenBracket),
+            ')' => tokens.push(Token::CloseBracket),
+            '0'..='9' =>
+            {
+                /* gather up the whole number, including prefix, separators, and suffix */
+                let mut number = String::from(c);
+                while let Some(&next) = chars.peek()
+                {
+                    if !next.is_ascii_alphanumeric() && next != '_'
+                    {
+                        break;
+                    }
+                    number.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Number(parse_number(&number)?));
+            },
+            'A'..='Z' | 'a'..='z' | '_' | '.' | '$' =>
+            {
+                /* gather up a function or section name */
+                let mut name = String::from(c);
+                while let Some(&next) = chars.peek()
+                {
+                    if !next.is_ascii_alphanumeric() && !"_.$".contains(next)
+                    {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            },
+            other => return Err(format!("Unexpected character '{}'", other))
+        }
+    }
+
+    Ok(tokens)
+}
+
+/* convert a number, with optional base prefix, _ separators, and K/M/G suffix, into a value */
+fn parse_number(number: &str) -> Result<u64, String>
+{
+    let digits: String = number.chars().filter(|c| *c != '_').collect();
+
+    /* strip any size suffix, leaving hex digits alone */
+    let (digits, multiplier) = match digits.chars().last()
+    {
+        Some('K') | Some('k') => (&digits[..digits.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&digits[..digits.len() - 1], 1 << 30),
+        _ => (&digits[..], 1)
+    };
+
+    let (digits, radix) = match digits.get(0..2)
+    {
+        Some("0x") | Some("0X") => (&digits[2..], 16),
+        Some("0o") | Some("0O") => (&digits[2..], 8),
+        Some("0b") | Some("0B") => (&digits[2..], 2),
+        _ => (digits, 10)
+    };
+
+    let value = match u64::from_str_radix(digits, radix)
+    {
+        Ok(value) => value,
+        Err(reason) => return Err(format!("Bad number '{}': {}", number, reason))
+    };
+
+    value.checked_mul(multiplier).ok_or(format!("Number '{}' is too large", number))
+}
+
+/* recursive-descent parser that evaluates the tokens as it goes */
+struct Parser<'a>
+{
+    tokens: &'a [Token],
+    position: usize,
+    lookup: &'a dyn Lookup
+}
+
+impl Parser<'_>
+{
+    fn peek(&self) -> Option<&Token>
+    {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token>
+    {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /* sum := product { (+|-) product } */
+    fn sum(&mut self) -> Result<u64, String>
+    {
+        let mut value = self.product()?;
+
+        loop
+        {
+            match self.peek()
+            {
+                Some(Token::Add) =>
+                {
+                    self.next();
+                    value = value.checked_add(self.product()?).ok_or("Addition overflows 64 bits")?;
+                },
+                Some(Token::Subtract) =>
+                {
+                    self.next();
+                    value = value.checked_sub(self.product()?).ok_or("Subtraction underflows zero")?;
+                },
+                _ => return Ok(value)
+            }
+        }
+    }
+
+    /* product := term { (*|/) term } */
+    fn product(&mut self) -> Result<u64, String>
+    {
+        let mut value = self.term()?;
+
+        loop
+        {
+            match self.peek()
+            {
+                Some(Token::Multiply) =>
+                {
+                    self.next();
+                    value = value.checked_mul(self.term()?).ok_or("Multiplication overflows 64 bits")?;
+                },
+                Some(Token::Divide) =>
+                {
+                    self.next();
+                    value = value.checked_div(self.term()?).ok_or("Division by zero")?;
+                },
+                _ => return Ok(value)
+            }
+        }
+    }
+
+    /* term := number | function ( name ) | symbol | ( sum ) */
+    fn term(&mut self) -> Result<u64, String>
+    {
+        match self.next()
+        {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Name(function)) if self.peek() == Some(&Token::OpenBracket) => match (self.next(), self.next(), self.next())
+            {
+                (Some(Token::OpenBracket), Some(Token::Name(name)), Some(Token::CloseBracket)) =>
+                    self.lookup.function(&function, &name),
+                _ => Err(format!("Expected {}(<name>)", function))
+            },
+            Some(Token::Name(symbol)) => self.lookup.symbol(&symbol),
+            Some(Token::OpenBracket) =>
+            {
+                let value = self.sum()?;
+                match self.next()
+                {
+                    Some(Token::CloseBracket) => Ok(value),
+                    _ => Err(String::from("Missing closing bracket"))
+                }
+            },
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err(String::from("Unexpected end of expression"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /* look up symbols and ADDR() from fixed values */
+    struct TestLookup;
+
+    impl Lookup for TestLookup
+    {
+        fn function(&self, function: &str, name: &str) -> Result<u64, String>
+        {
+            match (function, name)
+            {
+                ("ADDR", ".text") => Ok(0x8000_0000),
+                _ => Err(format!("{}({}) isn't defined", function, name))
+            }
+        }
+
+        fn symbol(&self, name: &str) -> Result<u64, String>
+        {
+            match name
+            {
+                "_stack_top" => Ok(0x1000),
+                _ => Err(format!("Symbol {} isn't defined", name))
+            }
+        }
+    }
+
+    #[test]
+    fn parse_number_bases()
+    {
+        assert_eq!(parse_number("1234"), Ok(1234));
+        assert_eq!(parse_number("0xffffffff80000000"), Ok(0xffff_ffff_8000_0000));
+        assert_eq!(parse_number("0XAb"), Ok(0xab));
+        assert_eq!(parse_number("0o17"), Ok(0o17));
+        assert_eq!(parse_number("0b1010"), Ok(0b1010));
+    }
+
+    #[test]
+    fn parse_number_separators_and_suffixes()
+    {
+        assert_eq!(parse_number("0x8000_0000"), Ok(0x8000_0000));
+        assert_eq!(parse_number("1_000_000"), Ok(1_000_000));
+        assert_eq!(parse_number("4K"), Ok(4 << 10));
+        assert_eq!(parse_number("2m"), Ok(2 << 20));
+        assert_eq!(parse_number("1G"), Ok(1 << 30));
+        assert_eq!(parse_number("0x10K"), Ok(0x10 << 10));
+
+        /* K, M, and G aren't hex digits, so hex numbers take suffixes too, and a
+           trailing hex digit such as b is part of the number rather than a suffix */
+        assert_eq!(parse_number("0xBK"), Ok(0xb << 10));
+        assert_eq!(parse_number("0x1G"), Ok(1 << 30));
+        assert_eq!(parse_number("0xb"), Ok(0xb));
+    }
+
+    #[test]
+    fn parse_number_errors()
+    {
+        assert!(parse_number("0x").is_err());
+        assert!(parse_number("12z").is_err());
+        assert!(parse_number("0b102").is_err());
+        assert!(parse_number("0x1_0000_0000_0000_0000").is_err());
+        assert!(parse_number("0xffff_ffff_ffff_ffffK").is_err());
+    }
+
+    #[test]
+    fn evaluate_precedence_and_brackets()
+    {
+        assert_eq!(evaluate("2 + 3 * 4", &NoLookup), Ok(14));
+        assert_eq!(evaluate("(2 + 3) * 4", &NoLookup), Ok(20));
+        assert_eq!(evaluate("100 - 10 - 1", &NoLookup), Ok(89));
+        assert_eq!(evaluate("64 / 4 / 2", &NoLookup), Ok(8));
+        assert_eq!(evaluate("0x8000_0000 + 2M", &NoLookup), Ok(0x8020_0000));
+        assert!(evaluate("(1 + 2", &NoLookup).is_err());
+        assert!(evaluate("1 +", &NoLookup).is_err());
+        assert!(evaluate("1 2", &NoLookup).is_err());
+    }
+
+    #[test]
+    fn evaluate_arithmetic_errors()
+    {
+        assert!(evaluate("1 / 0", &NoLookup).is_err());
+        assert!(evaluate("1 - 2", &NoLookup).is_err());
+        assert!(evaluate("0xffff_ffff_ffff_ffff + 1", &NoLookup).is_err());
+        assert!(evaluate("0x1_0000_0000 * 0x1_0000_0000", &NoLookup).is_err());
+    }
+
+    #[test]
+    fn evaluate_names()
+    {
+        assert_eq!(evaluate("_stack_top - 4", &TestLookup), Ok(0xffc));
+        assert_eq!(evaluate("ADDR(.text) + 0x100", &TestLookup), Ok(0x8000_0100));
+        assert!(evaluate("undefined_symbol", &TestLookup).is_err());
+        assert!(evaluate("ADDR(.text", &TestLookup).is_err());
+
+        /* names can't be used without a lookup that knows them */
+        assert!(evaluate("_stack_top", &NoLookup).is_err());
+        assert!(evaluate("ADDR(.text)", &NoLookup).is_err());
+    }
+}
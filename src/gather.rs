@@ -6,31 +6,122 @@
  */
 
 use super::manifest::{ self, Manifest, FileIdentifier };
-use super::config:: { Config, ExecutablePlacement };
+use super::config:: { Config, ExecutablePlacement, Region, OrphanPolicy };
+use super::expr::{ Expression, Lookup };
+use super::relocate::{ self, SectionRelaxation, GotEntry, DynamicReloc };
+use super::attributes::{ self, Attributes };
 
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::ops::Range;
 use wildmatch::WildMatch;
 use indexmap::set::IndexSet;
-use object::{ Object, ObjectSection, SectionIndex };
+use indexmap::map::IndexMap;
+use object::{ Object, ObjectSection, ObjectSymbol, SectionIndex, SymbolKind };
 
-const STANDARD_SECTIONS: [(&str, SectionSegment); 4] =
+/* the thread-local storage template (tdata, tbss) comes before the other writeable data.
+   small data (sdata, sbss) sits between data and bss so that it can all be reached
+   from the global pointer, which points 2KiB into the small data */
+const STANDARD_SECTIONS: [(&str, SectionSegment); 8] =
 [
     ("text",   SectionSegment::LoadableReadExec),
     ("rodata", SectionSegment::LoadableRead),
+    ("tdata",  SectionSegment::LoadableReadWrite),
+    ("tbss",   SectionSegment::LoadableReadWrite),
     ("data",   SectionSegment::LoadableReadWrite),
+    ("sdata",  SectionSegment::LoadableReadWrite),
+    ("sbss",   SectionSegment::LoadableReadWrite),
     ("bss",    SectionSegment::LoadableReadWrite)
 ];
 
+/* sections generated by the linker rather than gathered from object files. each gets its
+   own output section, placed directly after the standard section it's listed with */
+const SYNTHETIC_SECTIONS: [(SyntheticSection, &str, &str); 3] =
+[
+    (SyntheticSection::RelaDyn, ".rela.dyn", "rodata"),
+    (SyntheticSection::Got,     ".got",      "data"),
+    (SyntheticSection::Dynamic, ".dynamic",  "data")
+];
+
+/* the symbol marking the dynamic section of a relocatable executable, so that code can relocate itself */
+const DYNAMIC_SYMBOL: &str = "_DYNAMIC";
+
+/* a relocatable executable's dynamic section lists its dynamic relocations' address, total size, entry
+   size, and count, followed by a terminating entry. each entry is a tag and a value, one word each */
+const DYNAMIC_RELA_ENTRIES: u64 = 4;
+const DYNAMIC_ENTRY_WORDS: u64 = 2;
+
+/* files embedded by the config are placed after this standard section, as read-only data */
+const EMBED_PARENT: &str = "rodata";
+
+/* non-allocated input sections with names starting with this hold debugging information, eg: .debug_info */
+const DEBUG_SECTION_PREFIX: &str = ".debug";
+
+/* a GOT entry holds one address, and an Elf32_Rela or Elf64_Rela dynamic relocation three words */
+pub const RELA_ENTRY_WORDS: u64 = 3;
+
+/* thread-local storage sections, which together form the template for each thread's
+   TLS block. thread-local variables are addressed relative to the start of the template */
+const TLS_SECTIONS: [&str; 2] = [ "tdata", "tbss" ];
+
+/* small data sections, which are kept within reach of the global pointer */
+const SMALL_DATA_SECTIONS: [&str; 2] = [ "sdata", "sbss" ];
+
+/* the symbol RISC-V code uses to initialize gp, and how far into the small data it points */
+const GLOBAL_POINTER_SYMBOL: &str = "__global_pointer$";
+const GLOBAL_POINTER_OFFSET: u64 = 0x800;
+
 /* describe a segment into which sections are grouped */
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
-enum SectionSegment
+pub enum SectionSegment
 {
     LoadableRead,
     LoadableReadWrite,
     LoadableReadExec
 }
 
+impl SectionSegment
+{
+    /* return the memory region attributes needed to hold this segment */
+    fn required_attributes(&self) -> &'static str
+    {
+        match self
+        {
+            SectionSegment::LoadableRead => "r",
+            SectionSegment::LoadableReadWrite => "rw",
+            SectionSegment::LoadableReadExec => "rx"
+        }
+    }
+}
+
+/* describe the kinds of section generated by the linker */
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SyntheticSection
+{
+    Got,        /* global offset table: addresses of symbols accessed via R_RISCV_GOT_HI20 */
+    RelaDyn,    /* R_RISCV_RELATIVE relocations for a relocatable executable's GOT entries and absolute addresses */
+    Dynamic,    /* dynamic section telling a loader where to find a relocatable executable's relocations */
+    Embed(usize) /* files embedded by the config, as an index into the collection's embedded sections */
+}
+
+/* a file embedded in the executable by the config, and its offset within its output section */
+struct EmbeddedFile
+{
+    symbol: String,     /* prefix of the symbols marking the file, eg: _binary_guest_bin */
+    contents: Vec<u8>,
+    offset: u64
+}
+
+/* an output section holding embedded files */
+struct EmbeddedSection
+{
+    name: String,
+    files: Vec<EmbeddedFile>,
+    alignment: u64,
+    size: u64
+}
+
 /* describe a section within an object within the manifest */
-#[derive(PartialEq, Eq, Hash)]
 struct ManifestSection
 {
     pub identifier: FileIdentifier,
@@ -38,6 +129,78 @@ struct ManifestSection
     pub parent: usize
 }
 
+/* an input section belongs to exactly one standard section, so identify it
+   only by its object file and index, and not by its parent standard section */
+impl PartialEq for ManifestSection
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.identifier == other.identifier && self.index == other.index
+    }
+}
+
+impl Eq for ManifestSection {}
+
+impl std::hash::Hash for ManifestSection
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H)
+    {
+        self.identifier.hash(state);
+        self.index.hash(state);
+    }
+}
+
+/* describe a pattern that selects input sections by name, and optionally by the
+   pseudo-path of the object file containing them, in the form file(section), eg:
+   ".text*" selects all sections starting .text
+   "*rt0.o(.text*)" selects sections starting .text in any object file ending rt0.o
+   "libkernel*.rlib/entry*(.text*)" selects .text sections in libkernel*.rlib's entry objects */
+struct InputPattern
+{
+    file: Option<WildMatch>,
+    section: WildMatch
+}
+
+impl InputPattern
+{
+    pub fn new(pattern: &str) -> InputPattern
+    {
+        match (pattern.find('('), pattern.ends_with(')'))
+        {
+            (Some(open), true) => InputPattern
+            {
+                file: Some(WildMatch::new(&pattern[..open])),
+                section: WildMatch::new(&pattern[open + 1..pattern.len() - 1])
+            },
+            _ => InputPattern
+            {
+                file: None,
+                section: WildMatch::new(pattern)
+            }
+        }
+    }
+
+    /* return true if the given section in the given object file matches this pattern.
+       a file pattern can match the whole pseudo-path or any part of it following a /
+       so that patterns don't have to account for the directories objects are found in */
+    pub fn matches(&self, identifier: &FileIdentifier, section_name: &str) -> bool
+    {
+        if !self.section.matches(section_name)
+        {
+            return false;
+        }
+
+        let file = match &self.file
+        {
+            Some(file) => file,
+            None => return true
+        };
+
+        let path = identifier.to_string_lossy();
+        file.matches(&path) || path.match_indices('/').any(|(idx, _)| file.matches(&path[idx + 1..]))
+    }
+}
+
 /* describe an ordered section in memory */
 struct OrderedSection
 {
@@ -46,13 +209,77 @@ struct OrderedSection
     pub base_virt_addr: u64
 }
 
+/* a non-allocated output section of debugging information, eg: .debug_line, made by concatenating
+   the object files' input sections of the same name. it isn't loaded, so it has no address */
+pub struct DebugSection
+{
+    pub name: String,
+    pub alignment: u64,
+    pub size: u64,
+    inputs: Vec<(FileIdentifier, SectionIndex, u64)> /* input sections, and their offsets within this section */
+}
+
+/* describe a standard section once its input sections have been arranged in memory */
+pub struct OutputSection
+{
+    pub name: String,           /* name of the section in the executable, eg: .text */
+    pub segment: SectionSegment,
+    pub base_phys_addr: u64,    /* where the section is loaded in memory (LMA) */
+    pub base_virt_addr: u64,    /* where the section runs in memory (VMA) */
+    pub size: u64,
+    pub alignment: u64,
+    pub nobits: bool,           /* true if the section takes up no space in the file, eg: .bss */
+    pub ordered: Range<usize>,  /* the input sections in this section, as indices into the ordered list */
+    pub synthetic: Option<SyntheticSection>, /* set if the section is generated by the linker */
+    pub tls: bool               /* true if the section is part of the thread-local storage template */
+}
+
+impl OutputSection
+{
+    /* uninitialized thread-local data, eg: .tbss, only sizes the TLS template. each thread's copy
+       of it is set up elsewhere, so it takes up no memory where it's placed and isn't loaded */
+    pub fn occupies_memory(&self) -> bool { !(self.tls && self.nobits) }
+}
+
+/* describe where a global symbol is defined */
+#[derive(Clone, Copy)]
+pub enum SymbolDefinition
+{
+    Section(usize, u64),        /* index into the gathered sections, and offset within that section */
+    Absolute(u64)               /* fixed value, eg: a symbol defined by the linker */
+}
+
+/* describe a global symbol available to all objects. symbols local to an object are described the same way */
+#[derive(Clone, Copy)]
+pub struct GlobalSymbol
+{
+    pub definition: SymbolDefinition,
+    pub kind: SymbolKind,
+    pub size: u64,
+    pub weak: bool
+}
+
 /* describe the gathered up components */
 pub struct Collection
 {
     sections: IndexSet<ManifestSection>,
     merged: Vec<Vec<usize>>,
     ordered: Vec<OrderedSection>,
-    e_flags: object::FileFlags
+    placement: Vec<usize>,
+    outputs: Vec<OutputSection>,
+    relaxations: Vec<SectionRelaxation>,
+    got: IndexMap<GotEntry, bool>,  /* GOT entries, and whether each needs a RELATIVE dynamic relocation */
+    dynamic: Vec<DynamicReloc>,     /* absolute addresses in a relocatable executable that need RELATIVE dynamic relocations */
+    symbols: IndexMap<String, GlobalSymbol>,
+    locals: Vec<(String, GlobalSymbol)>,   /* symbols local to their object files, for the symbol table */
+    e_flags: object::FileFlags,
+    attributes: Option<Attributes>,
+    embedded: Vec<EmbeddedSection>,
+    debug: Vec<DebugSection>,
+    debug_offsets: HashMap<(FileIdentifier, SectionIndex), u64>, /* offset of each debugging input section in its output section */
+    assignments: Vec<(String, Expression)>, /* symbols defined by the config, and their values */
+    wrapped: Vec<String>,   /* symbols whose undefined references are redirected by --wrap */
+    is_64: bool     /* true for an RV64 executable, false for RV32 */
 }
 
 impl Collection
@@ -60,13 +287,20 @@ impl Collection
     /* collect up the required sections and symbols given the manifest and configuration */
     pub fn new(config: &Config, manifest: &Manifest) -> Collection
     {
-        /* keep track of sections, symbols, and flags we're interested in.
+        /* keep track of sections and symbols we're interested in.
            preserve insertion order as that's important for sections at least */
         let mut sections = IndexSet::new();
-        let mut e_flags = object::FileFlags::None;
+
+        /* an input section goes in the first standard section with a pattern that matches it.
+           note the pattern that claimed each section so later matches can be reported */
+        let mut claimed_by: Vec<&String> = Vec::new();
+        let warn_conflicts = config.get_output().warn_section_conflicts();
+
+        /* note the input sections left out by exclude patterns so they aren't picked up as orphans */
+        let mut excluded: Vec<(FileIdentifier, SectionIndex)> = Vec::new();
 
         /* the link configuration file groups sections to include into
-           blocks of standard sections (text, rodata, data, bss). iterate over
+           blocks of standard sections (text, rodata, tdata, tbss, data, sdata, sbss, bss). iterate over
            the standard sections in the config, scanning the manifest's object files
            for sections that match the sections specified in the block */
         for standard_section_idx in 0..STANDARD_SECTIONS.len()
@@ -75,15 +309,20 @@ impl Collection
 
             if let Some(section_group) = config.get_sections().get(standard_section)
             {
+                let excludes: Vec<InputPattern> = match section_group.get_sections_to_exclude()
+                {
+                    Some(excludes) => excludes.iter().map(|exclude| InputPattern::new(exclude)).collect(),
+                    None => Vec::new()
+                };
+
                 for section_to_include in section_group.get_sections_to_include().iter()
                 {
-                    let pattern = WildMatch::new(section_to_include);
+                    let pattern = InputPattern::new(section_to_include);
 
                     /* spin through the memory-mapped object files in the manifest and
                        their sections for matching sections to include */
                     for (obj_name, mapping) in manifest.raw_objects()
                     {
-                        let mut flags_updated = false;
                         let parsed = manifest::parse(mapping);
 
                         /* TODO: support comdats? */
@@ -104,23 +343,38 @@ impl Collection
                             let kind = section.kind();
 
                             /* does the section match the section name we're interested in? */
-                            if pattern.matches(name) && kind != object::SectionKind::Metadata
+                            if !pattern.matches(obj_name, name) || kind == object::SectionKind::Metadata
                             {
-                                /* if so, try to insert it */
-                                if sections.insert(ManifestSection
+                                continue;
+                            }
+
+                            /* if it's excluded, note it so it isn't placed as an orphan. otherwise try to insert it */
+                            if excludes.iter().any(|exclude| exclude.matches(obj_name, name))
+                            {
+                                excluded.push((obj_name.to_path_buf(), section.index()));
+                            }
+                            else
+                            {
+                                let (section_idx, inserted) = sections.insert_full(ManifestSection
                                 {
                                     identifier: obj_name.to_path_buf(),
                                     index: section.index(),
                                     parent: standard_section_idx
-                                })
-                                {                                    
-                                    /* if we're here then the insertion was successful.
-                                       update the e_flags once per object file */
-                                    if flags_updated == false
-                                    {
-                                        e_flags = update_e_flags(e_flags, parsed.flags());
-                                        flags_updated = true;
-                                    }
+                                });
+
+                                if inserted
+                                {
+                                    /* if we're here then the insertion was successful */
+                                    claimed_by.push(section_to_include);
+                                }
+                                else if warn_conflicts && sections[section_idx].parent != standard_section_idx
+                                {
+                                    /* the section was already claimed by an earlier standard section */
+                                    eprintln!("Warning: Section {} in {} matches pattern '{}' for .{} and pattern '{}' for .{}: placing it in .{}",
+                                        name, obj_name.to_str().unwrap(),
+                                        claimed_by[section_idx], STANDARD_SECTIONS[sections[section_idx].parent].0,
+                                        section_to_include, standard_section,
+                                        STANDARD_SECTIONS[sections[section_idx].parent].0);
                                 }
                             }
                         }
@@ -129,9 +383,102 @@ impl Collection
             }
         }
 
+        /* pick up any orphans: allocatable input sections not matched by any pattern, or excluded by one */
+        let orphan_policy = config.get_output().get_orphan_policy();
+        let mut orphan_count = 0;
+        for (obj_name, mapping) in manifest.raw_objects()
+        {
+            let parsed = manifest::parse(mapping);
+
+            for section in parsed.sections()
+            {
+                let sh_flags = match section.flags()
+                {
+                    object::SectionFlags::Elf { sh_flags } => sh_flags,
+                    _ => 0
+                };
+
+                /* skip sections that won't take up any memory, and those deliberately left out */
+                if sh_flags & object::elf::SHF_ALLOC as u64 == 0 || section.size() == 0 || section.kind() == object::SectionKind::Metadata ||
+                    excluded.iter().any(|(identifier, index)| identifier == obj_name && *index == section.index())
+                {
+                    continue;
+                }
+
+                let (_, inserted) = sections.insert_full(ManifestSection
+                {
+                    identifier: obj_name.to_path_buf(),
+                    index: section.index(),
+                    parent: orphan_parent(section.name().unwrap_or(""), sh_flags, section.kind())
+                });
+
+                if inserted
+                {
+                    let name = section.name().unwrap_or("");
+                    let parent = STANDARD_SECTIONS[orphan_parent(name, sh_flags, section.kind())].0;
+                    match orphan_policy
+                    {
+                        OrphanPolicy::Place => (),
+                        OrphanPolicy::Warn => eprintln!("Warning: Section {} in {} isn't matched by any include pattern: placing it in .{}",
+                                                name, obj_name.to_str().unwrap(), parent),
+                        OrphanPolicy::Error => eprintln!("Section {} in {} isn't matched by any include pattern",
+                                                name, obj_name.to_str().unwrap())
+                    }
+
+                    orphan_count += 1;
+                }
+            }
+        }
+
+        if orphan_policy == OrphanPolicy::Error && orphan_count > 0
+        {
+            fatal_msg!("Found {} input section(s) not matched by any include pattern", orphan_count);
+        }
+
+        let is_64 = select_width(config, &sections, manifest);
+        let e_flags = merge_e_flags(config, &sections, manifest);
+        let attributes = gather_attributes(config, &sections, manifest);
+        let symbols = gather_symbols(&sections, manifest);
+        let locals = gather_local_symbols(&sections, manifest);
+        let embedded = gather_embedded(config);
+        let debug = gather_debug_sections(manifest);
+        let debug_offsets = debug.iter()
+            .flat_map(|output| output.inputs.iter())
+            .map(|(identifier, index, offset)| ((identifier.clone(), *index), *offset))
+            .collect();
+
+        /* every archive member is already linked in and no unreferenced sections are discarded, so -u
+           has nothing to pull in or keep alive. all it does is warn about symbols nothing defines */
+        for name in config.get_required_symbols()
+        {
+            let assigned = config.get_symbol_assignments().is_some_and(|assignments| assignments.contains_key(name));
+            if !symbols.contains_key(name) && !assigned
+            {
+                eprintln!("Warning: Symbol {} given with -u isn't defined by any object file", name);
+            }
+        }
+
+        /* note the symbols the config defines. those only provided if no object file defines
+           them are skipped if an object file does */
+        let assignments = match config.get_symbol_assignments()
+        {
+            Some(assignments) => assignments.iter()
+                .filter(|(name, assignment)| !assignment.is_provided() || !symbols.contains_key(*name))
+                .map(|(name, assignment)| (name.clone(), assignment.get_value().clone()))
+                .collect(),
+            None => Vec::new()
+        };
+
         Collection
         {
-            sections, e_flags, ordered: Vec::new(),
+            sections, symbols, locals, e_flags, attributes, embedded, debug, debug_offsets, assignments, is_64,
+            wrapped: config.get_wrapped_symbols().clone(),
+            ordered: Vec::new(),
+            placement: Vec::new(),
+            outputs: Vec::new(),
+            relaxations: Vec::new(),
+            got: IndexMap::new(),
+            dynamic: Vec::new(),
             merged:
             {
                 /* initialize array of standard section groups with empty queues */
@@ -156,18 +503,146 @@ impl Collection
         }
     }
 
-    /* arrange the merged sections into memory */
+    /* arrange the merged sections into memory, relaxing code as the sections are
+       laid out, until the code can't be relaxed any further. see relocate.rs */
     pub fn arrange(&mut self, config: &Config, manifest: &Manifest)
     {
-        let (mut phys_addr, mut virt_addr) = match config.get_output().get_placement()
+        self.relaxations = vec![SectionRelaxation::default(); self.sections.len()];
+        self.got = relocate::build_got(self, config, manifest);
+        self.dynamic = relocate::build_dynamic_relocs(self, config, manifest);
+
+        for _ in 0..relocate::MAX_RELAXATION_PASSES
+        {
+            self.layout(config, manifest);
+
+            /* laying out the sections defines symbols, eg: with --defsym, that can override those
+               from object files, so rebuild the GOT and dynamic relocations from the symbols as they are now */
+            let got = relocate::build_got(self, config, manifest);
+            let dynamic = relocate::build_dynamic_relocs(self, config, manifest);
+            let tables_settled = self.got.iter().eq(got.iter()) && self.dynamic.len() == dynamic.len();
+            self.got = got;
+            self.dynamic = dynamic;
+
+            /* stop once relaxation and the GOT no longer change the layout */
+            let relaxations = relocate::relax(self, config, manifest);
+            let settled = tables_settled && self.relaxations.iter().zip(relaxations.iter())
+                .all(|(before, after)| before.deletions == after.deletions);
+            self.relaxations = relaxations;
+
+            if settled
+            {
+                self.check_global_pointer();
+                self.report_arrangement(manifest);
+                return;
+            }
+        }
+
+        fatal_msg!("Code relaxation didn't settle after {} passes", relocate::MAX_RELAXATION_PASSES);
+    }
+
+    /* lay out the merged sections in memory. standard sections assigned to a
+       memory region are placed one after the other from the start of that region,
+       and the rest are placed one after the other from the executable's base address.
+       a standard section can also be given a separate load region, in which case
+       it is stored in its load region and copied to its run region at runtime */
+    fn layout(&mut self, config: &Config, manifest: &Manifest)
+    {
+        let (mut next_phys_addr, mut next_virt_addr) = match config.get_output().get_placement()
         {
             ExecutablePlacement::Relocatable => (0, 0), /* just start from zero for reloc ELFs */
             ExecutablePlacement::Static(phys, virt) => (phys, virt)
         };
 
+        /* keep track of the next free address in each memory region used,
+           and where each standard section ends up */
+        let mut region_addrs: HashMap<String, u64> = HashMap::new();
+        let mut placed = PlacedSections { sections: HashMap::new() };
+        /* symbols the linker defines as it places sections, eg: marking embedded files */
+        let mut generated_symbols: Vec<(String, u64)> = Vec::new();
+
+        self.ordered = Vec::new();
+        self.outputs = Vec::new();
+        self.placement = vec![0; self.sections.len()];
+
         for standard_section_idx in 0..self.merged.len()
         {
-            eprintln!("standard section: .{}:", STANDARD_SECTIONS[standard_section_idx].0);
+            let (standard_section_name, segment) = STANDARD_SECTIONS[standard_section_idx];
+            let section_group = config.get_sections().get(standard_section_name);
+
+            /* find the memory regions, if any, this standard section must run from and be loaded into */
+            let region = match section_group
+            {
+                Some(section_group) => lookup_region(config, standard_section_name, section_group.get_region(), segment.required_attributes()),
+                None => None
+            };
+            let load_region = match section_group
+            {
+                Some(section_group) => match lookup_region(config, standard_section_name, section_group.get_load_region(), "r")
+                {
+                    /* loading and running from the same region is the default */
+                    Some((name, _)) if Some(name) == region.map(|(name, _)| name) => None,
+                    other => other
+                },
+                None => None
+            };
+
+            /* figure out where the section will run from and where it will be loaded */
+            let mut virt_addr = match region
+            {
+                Some((name, region)) => *region_addrs.entry(name.clone()).or_insert(region.get_origin(name)),
+                None => next_virt_addr
+            };
+            let mut phys_addr = match (load_region, region)
+            {
+                (Some((name, region)), _) => *region_addrs.entry(name.clone()).or_insert(region.get_origin(name)),
+                (None, Some(_)) => virt_addr, /* sections in a region run from where they're loaded */
+                (None, None) => next_phys_addr
+            };
+
+            /* honor any fixed run address. the section is loaded at the same address unless
+               it has a separate load region, or the executable's load and run addresses differ */
+            if let Some(address) = section_group.and_then(|section_group| section_group.get_address().as_ref())
+            {
+                let address = address.evaluate_with(&format!("section.{}.address", standard_section_name), &placed);
+                if let Some((name, region)) = region
+                {
+                    if address < region.get_origin(name)
+                    {
+                        fatal_msg!("Section .{} address 0x{:x} lies below the start of memory region {}",
+                            standard_section_name, address, name);
+                    }
+                }
+
+                phys_addr = match (load_region, region)
+                {
+                    (Some(_), _) => phys_addr,
+                    (None, Some(_)) => address,
+                    (None, None) => address.wrapping_add(next_phys_addr.wrapping_sub(next_virt_addr))
+                };
+                virt_addr = address;
+            }
+
+            /* align the start of the section to its most-aligned input section. the TLS template
+               as a whole is aligned to its most-aligned input section, so that variables
+               addressed relative to its start stay aligned wherever the template is copied */
+            let tls = TLS_SECTIONS.contains(&standard_section_name);
+            let alignment = (0..STANDARD_SECTIONS.len())
+                .filter(|idx| *idx == standard_section_idx || (tls && TLS_SECTIONS.contains(&STANDARD_SECTIONS[*idx].0)))
+                .flat_map(|idx| self.merged[idx].iter())
+                .map(|section_idx| self.input_section_alignment(*section_idx, manifest))
+                .max().unwrap_or(1);
+            let (start_phys_addr, start_virt_addr) = (align_to(phys_addr, alignment), align_to(virt_addr, alignment));
+            if section_group.and_then(|section_group| section_group.get_address().as_ref()).is_some() && start_virt_addr != virt_addr
+            {
+                fatal_msg!("Section .{} address 0x{:x} isn't aligned to its required {}-byte alignment",
+                    standard_section_name, virt_addr, alignment);
+            }
+            let mut nobits = true;
+            let first_ordered = self.ordered.len();
+
+            phys_addr = start_phys_addr;
+            virt_addr = start_virt_addr;
+
             let standard_section = &self.merged[standard_section_idx];
             for merged_section_idx in 0..standard_section.len()
             {
@@ -189,6 +664,7 @@ impl Collection
 
                 phys_addr = align_to(phys_addr, section.align());
                 virt_addr = align_to(virt_addr, section.align());
+                self.placement[section_idx] = self.ordered.len();
                 self.ordered.push(OrderedSection
                 {
                     section_index: section_idx,
@@ -196,73 +672,1190 @@ impl Collection
                     base_virt_addr: virt_addr
                 });
 
-                eprintln!("  0x{:x}: {}", virt_addr, parsed.section_by_index(self.sections[section_idx].index).unwrap().name().unwrap_or(""));
+                if section.kind() != object::SectionKind::UninitializedData && section.kind() != object::SectionKind::UninitializedTls
+                {
+                    nobits = false;
+                }
 
-                let size = section.size();
+                let size = section.size() - self.relaxations[section_idx].deleted();
                 phys_addr = phys_addr + size;
                 virt_addr = virt_addr + size;
             }
+
+            let size = virt_addr - start_virt_addr;
+            placed.sections.insert(String::from(standard_section_name), (start_phys_addr, start_virt_addr, size));
+            if self.ordered.len() > first_ordered
+            {
+                self.outputs.push(OutputSection
+                {
+                    name: format!(".{}", standard_section_name),
+                    segment, alignment, nobits, size,
+                    base_phys_addr: start_phys_addr,
+                    base_virt_addr: start_virt_addr,
+                    ordered: first_ordered..self.ordered.len(),
+                    synthetic: None,
+                    tls
+                });
+            }
+
+            /* follow the standard section with any linker-generated sections listed with it */
+            for (synthetic, name, _) in SYNTHETIC_SECTIONS.iter().filter(|(_, _, follows)| *follows == standard_section_name)
+            {
+                let synthetic_size = self.synthetic_size(*synthetic, config);
+                if synthetic_size == 0
+                {
+                    continue;
+                }
+
+                phys_addr = align_to(phys_addr, self.address_size());
+                virt_addr = align_to(virt_addr, self.address_size());
+                self.outputs.push(OutputSection
+                {
+                    name: String::from(*name),
+                    segment,
+                    base_phys_addr: phys_addr,
+                    base_virt_addr: virt_addr,
+                    size: synthetic_size,
+                    alignment: self.address_size(),
+                    nobits: false,
+                    ordered: self.ordered.len()..self.ordered.len(),
+                    synthetic: Some(*synthetic),
+                    tls: false
+                });
+
+                if *synthetic == SyntheticSection::Dynamic
+                {
+                    generated_symbols.push((String::from(DYNAMIC_SYMBOL), virt_addr));
+                }
+
+                phys_addr += synthetic_size;
+                virt_addr += synthetic_size;
+                nobits = false;
+            }
+
+            /* follow the read-only data with the sections of embedded files, defining symbols
+               that mark the start, end, and size of each file */
+            if standard_section_name == EMBED_PARENT
+            {
+                for (embedded_idx, embedded) in self.embedded.iter().enumerate()
+                {
+                    phys_addr = align_to(phys_addr, embedded.alignment);
+                    virt_addr = align_to(virt_addr, embedded.alignment);
+                    self.outputs.push(OutputSection
+                    {
+                        name: embedded.name.clone(),
+                        segment,
+                        base_phys_addr: phys_addr,
+                        base_virt_addr: virt_addr,
+                        size: embedded.size,
+                        alignment: embedded.alignment,
+                        nobits: false,
+                        ordered: self.ordered.len()..self.ordered.len(),
+                        synthetic: Some(SyntheticSection::Embed(embedded_idx)),
+                        tls: false
+                    });
+                    placed.sections.insert(String::from(embedded.name.trim_start_matches('.')), (phys_addr, virt_addr, embedded.size));
+
+                    for file in &embedded.files
+                    {
+                        let start = virt_addr + file.offset;
+                        let size = file.contents.len() as u64;
+                        generated_symbols.push((format!("{}_start", file.symbol), start));
+                        generated_symbols.push((format!("{}_end", file.symbol), start + size));
+                        generated_symbols.push((format!("{}_size", file.symbol), size));
+                    }
+
+                    phys_addr += embedded.size;
+                    virt_addr += embedded.size;
+                    nobits = false;
+                }
+            }
+
+            /* the next section starts where uninitialized thread-local data does */
+            if tls && nobits
+            {
+                phys_addr = start_phys_addr;
+                virt_addr = start_virt_addr;
+            }
+
+            /* check the standard section fits in its regions, and note where the next one can go.
+               uninitialized data isn't stored anywhere so it takes up no space in its load region */
+            if let Some((name, region)) = region
+            {
+                check_region_overflow(standard_section_name, name, region, virt_addr);
+                region_addrs.insert(name.clone(), virt_addr);
+            }
+            else
+            {
+                next_virt_addr = virt_addr;
+            }
+
+            match (load_region, region)
+            {
+                (Some((name, load_region)), _) => if !nobits
+                {
+                    check_region_overflow(standard_section_name, name, load_region, phys_addr);
+                    region_addrs.insert(name.clone(), phys_addr);
+                },
+                (None, Some(_)) => (),
+                (None, None) => next_phys_addr = phys_addr
+            }
+
+            /* define any symbols marking the boundaries of this standard section */
+            if let Some(section_group) = section_group
+            {
+                for (symbol, value) in
+                [
+                    (section_group.get_start_symbol(), start_virt_addr),
+                    (section_group.get_end_symbol(), start_virt_addr + size),
+                    (section_group.get_load_start_symbol(), start_phys_addr),
+                    (section_group.get_load_end_symbol(), start_phys_addr + size)
+                ]
+                {
+                    if let Some(symbol) = symbol
+                    {
+                        self.define_symbol(symbol, value);
+                    }
+                }
+            }
         }
-    }
-}
 
-/* align the given address up to the next power-of-two alignment, if necessary */
-fn align_to(address: u64, alignment: u64) -> u64
-{
-    let align_down = address & !(alignment - 1);
-    
-    if align_down == address
-    {
-        address
-    }
-    else
-    {
-        align_down + alignment
-    }
-}
+        for (symbol, value) in generated_symbols
+        {
+            self.define_symbol(&symbol, value);
+        }
 
-/* define e_flags bit position meanings */
-const EF_RVC: u32 = 0;                  /* bit    0 = C ext (compressed instructions) in use */
-const EF_FLOAT_ABI: u32 = 1;            /* bits 1-2 = float ABI level */
-const EF_FLOAT_ABI_MASK: u32 = 0b11;
-const EF_FLOAT_ABI_MASK_SHIFTED: u32 = EF_FLOAT_ABI_MASK << EF_FLOAT_ABI;
-const EF_RVE: u32 = 3;                  /* bit    3 = RISC-V EABI in use */
-const EF_TSO: u32 = 4;                  /* bit    4 = RVTSO memory consistency model required */
+        self.check_overlaps();
+        self.check_address_width();
 
-/* summarize usage flag bitmask in e_flags */
-const EF_USAGE_FLAGS: u32 = (1 << EF_RVC) | (1 << EF_RVE) | (1 << EF_TSO);
- 
-/* update the given ELF file e_flags with the given object file e_flags.
-   these flags are processor architecture (RISC-V) dependent and are
-   defined here: https://github.com/riscv-non-isa/riscv-elf-psabi-doc */
-fn update_e_flags(current_flags: object::FileFlags, obj_flags: object::FileFlags) -> object::FileFlags
-{
-    let obj_flags = match obj_flags
-    {
-        object::FileFlags::None => 0,
-        object::FileFlags::Elf { e_flags } => e_flags,
-        other => fatal_msg!("Unexpected error: unrecognized object flags {:?}", other)
-    };
+        /* point gp 2KiB into the small data, or into the data if there's no small data,
+           unless an object file defines the global pointer itself. the TLS template is
+           addressed from tp, so it's never used as the data */
+        if config.get_output().define_global_pointer() &&
+            !matches!(self.symbols.get(GLOBAL_POINTER_SYMBOL).map(|symbol| symbol.definition), Some(SymbolDefinition::Section(_, _)))
+        {
+            let base = match self.outputs.iter().find(|output| SMALL_DATA_SECTIONS.contains(&&output.name[1..]))
+            {
+                Some(output) => Some(output),
+                None => self.outputs.iter().find(|output| output.segment == SectionSegment::LoadableReadWrite && !output.tls)
+            };
 
-    let mut elf_flags = match current_flags
-    {
-        object::FileFlags::None => 0,
-        object::FileFlags::Elf { e_flags } => e_flags,
-        other => fatal_msg!("Unexpected error: unrecognized ELF flags {:?}", other)
-    };
+            if let Some(base) = base
+            {
+                let value = base.base_virt_addr + GLOBAL_POINTER_OFFSET;
+                self.define_symbol(&String::from(GLOBAL_POINTER_SYMBOL), value);
+            }
+        }
+
+        /* define any symbols marking the boundaries of the whole executable */
+        let output = config.get_output();
+        if let (Some(symbol), Some(first)) = (output.get_start_symbol(), self.outputs.first())
+        {
+            self.define_symbol(symbol, first.base_virt_addr);
+        }
+        if let (Some(symbol), Some(last)) = (output.get_end_symbol(), self.outputs.last())
+        {
+            self.define_symbol(symbol, last.base_virt_addr + last.size);
+        }
 
-    /* set bits for compressed instruction, EABI, RVTSO memory model, etc usage */
-    elf_flags |= obj_flags & EF_USAGE_FLAGS;
+        /* define the symbols given values by the config, which can refer to each other in any order */
+        let values: Vec<(String, u64)> =
+        {
+            let lookup = AssignmentLookup { collection: self, placed: &placed, evaluating: RefCell::new(Vec::new()) };
+            self.assignments.iter().map(|(name, _)| match lookup.symbol(name)
+            {
+                Ok(value) => (name.clone(), value),
+                Err(reason) => fatal_msg!("Can't define symbols.{} in configuration file: {}", name, reason)
+            }).collect()
+        };
+        for (name, value) in values
+        {
+            self.define_symbol(&name, value);
+        }
+    }
 
-    /* set the floating-point ABI level. each level is backwards compatible.
-       ensure the elf's flags reflect the highest level used by its objects */
-    let obj_float_abi_level = obj_flags & EF_FLOAT_ABI_MASK_SHIFTED;
-    let elf_float_abi_level = elf_flags & EF_FLOAT_ABI_MASK_SHIFTED;
-    if obj_float_abi_level > elf_float_abi_level
+    /* bail out if any two output sections overlap where they run or where they're loaded */
+    fn check_overlaps(&self)
     {
-        elf_flags = obj_float_abi_level | (elf_flags & !EF_FLOAT_ABI_MASK_SHIFTED);
+        for (idx, first) in self.outputs.iter().enumerate()
+        {
+            for second in &self.outputs[idx + 1..]
+            {
+                for (description, first_start, second_start, loaded) in
+                [
+                    ("run", first.base_virt_addr, second.base_virt_addr, first.occupies_memory() && second.occupies_memory()),
+                    ("load", first.base_phys_addr, second.base_phys_addr, !first.nobits && !second.nobits)
+                ]
+                {
+                    let first_end = first_start as u128 + first.size as u128;
+                    let second_end = second_start as u128 + second.size as u128;
+                    if loaded && (first_start as u128) < second_end && (second_start as u128) < first_end
+                    {
+                        fatal_msg!("Section {} {} addresses 0x{:x}-0x{:x} overlap section {} at 0x{:x}-0x{:x}",
+                            first.name, description, first_start, first_end, second.name, second_start, second_end);
+                    }
+                }
+            }
+        }
     }
 
-    /* return the updated flag bits */
-    object::FileFlags::Elf { e_flags: elf_flags }
-}
\ No newline at end of file
+    /* bail out if an RV32 executable's sections don't fit in its 32-bit address space */
+    fn check_address_width(&self)
+    {
+        if self.is_64
+        {
+            return;
+        }
+
+        for output in &self.outputs
+        {
+            for (description, start) in [ ("run", output.base_virt_addr), ("load", output.base_phys_addr) ]
+            {
+                if start as u128 + output.size as u128 > 1 << 32
+                {
+                    fatal_msg!("Section {} {} addresses 0x{:x}-0x{:x} don't fit in the RV32 address space",
+                        output.name, description, start, start as u128 + output.size as u128);
+                }
+            }
+        }
+    }
+
+    /* warn if any small data lies out of reach of gp-relative accesses, which can
+       only address 2KiB either side of the global pointer */
+    fn check_global_pointer(&self)
+    {
+        let gp = match self.symbol_address(GLOBAL_POINTER_SYMBOL)
+        {
+            Some(gp) => gp,
+            None => return
+        };
+
+        for output in self.outputs.iter().filter(|output| SMALL_DATA_SECTIONS.contains(&&output.name[1..]))
+        {
+            let end = output.base_virt_addr + output.size;
+            if output.base_virt_addr < gp.saturating_sub(GLOBAL_POINTER_OFFSET) || end > gp.saturating_add(GLOBAL_POINTER_OFFSET)
+            {
+                eprintln!("Warning: Small data section {} at 0x{:x}-0x{:x} extends beyond 2KiB either side of {} at 0x{:x}",
+                    output.name, output.base_virt_addr, end, GLOBAL_POINTER_SYMBOL, gp);
+            }
+        }
+    }
+
+    /* define a symbol with an absolute value, replacing any existing definition */
+    fn define_symbol(&mut self, name: &str, value: u64)
+    {
+        self.symbols.insert(String::from(name), GlobalSymbol
+        {
+            definition: SymbolDefinition::Absolute(value),
+            kind: SymbolKind::Unknown,
+            size: 0,
+            weak: false
+        });
+    }
+
+    /* return the size of the given linker-generated section */
+    fn synthetic_size(&self, synthetic: SyntheticSection, config: &Config) -> u64
+    {
+        match synthetic
+        {
+            SyntheticSection::Got => self.got.len() as u64 * self.address_size(),
+            SyntheticSection::Embed(embedded_idx) => self.embedded[embedded_idx].size,
+
+            /* only relocatable executables need their GOT entries and absolute addresses adjusting at runtime */
+            SyntheticSection::RelaDyn => match config.get_output().get_placement()
+            {
+                ExecutablePlacement::Relocatable => self.dynamic_reloc_count() * RELA_ENTRY_WORDS * self.address_size(),
+                ExecutablePlacement::Static(_, _) => 0
+            },
+
+            /* the loader needs a dynamic section to find those adjustments, so always provide one when relocatable */
+            SyntheticSection::Dynamic => match config.get_output().get_placement()
+            {
+                ExecutablePlacement::Relocatable => match self.dynamic_reloc_count()
+                {
+                    0 => DYNAMIC_ENTRY_WORDS * self.address_size(),
+                    _ => (DYNAMIC_RELA_ENTRIES + 1) * DYNAMIC_ENTRY_WORDS * self.address_size()
+                },
+                ExecutablePlacement::Static(_, _) => 0
+            }
+        }
+    }
+
+    /* return the required alignment of the given input section */
+    fn input_section_alignment(&self, section_idx: usize, manifest: &Manifest) -> u64
+    {
+        let identifier = &self.sections[section_idx].identifier;
+        let parsed = match manifest.get(identifier)
+        {
+            Some(mapping) => manifest::parse(mapping),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?}", identifier)
+        };
+
+        match parsed.section_by_index(self.sections[section_idx].index)
+        {
+            Ok(section) => std::cmp::max(section.align(), 1),
+            Err(reason) => fatal_msg!("Unexpected error: Can't find section {} in {:?}: {}",
+                            self.sections[section_idx].index.0, identifier, reason)
+        }
+    }
+
+    /* print where each input section has been placed in memory */
+    fn report_arrangement(&self, manifest: &Manifest)
+    {
+        for output in &self.outputs
+        {
+            if output.synthetic.is_some()
+            {
+                eprintln!("linker section: {}: 0x{:x}", output.name, output.base_virt_addr);
+                continue;
+            }
+
+            eprintln!("standard section: {}:", output.name);
+            for ordered in &self.ordered[output.ordered.clone()]
+            {
+                let input = &self.sections[ordered.section_index];
+                let name = match manifest.get(&input.identifier)
+                {
+                    Some(mapping) => String::from(manifest::parse(mapping).section_by_index(input.index).unwrap().name().unwrap_or("")),
+                    None => String::new()
+                };
+                eprintln!("  0x{:x}: {}", ordered.base_virt_addr, name);
+            }
+        }
+    }
+
+    /* return the contents of the given output section, as laid out in memory,
+       with relocations applied. call arrange() before this */
+    pub fn output_section_data(&self, output: &OutputSection, config: &Config, manifest: &Manifest) -> Vec<u8>
+    {
+        match output.synthetic
+        {
+            Some(SyntheticSection::Embed(embedded_idx)) =>
+            {
+                let embedded = &self.embedded[embedded_idx];
+                let mut data = vec![0; embedded.size as usize];
+                for file in &embedded.files
+                {
+                    data[file.offset as usize..file.offset as usize + file.contents.len()].copy_from_slice(&file.contents);
+                }
+                return data;
+            },
+            Some(synthetic) => return relocate::synthetic_data(self, synthetic, manifest),
+            None => ()
+        }
+
+        let mut data = vec![0; output.size as usize];
+
+        for ordered in &self.ordered[output.ordered.clone()]
+        {
+            let contents = relocate::section_data(self, ordered.section_index, config, manifest);
+            let offset = (ordered.base_virt_addr - output.base_virt_addr) as usize;
+            data[offset..offset + contents.len()].copy_from_slice(&contents);
+        }
+
+        data
+    }
+
+    /* return the run address of the given offset within the given input section,
+       accounting for any bytes removed by relaxation. call arrange() before this */
+    pub fn address_of(&self, section_idx: usize, offset: u64) -> u64
+    {
+        self.ordered[self.placement[section_idx]].base_virt_addr + self.relaxations[section_idx].adjust(offset)
+    }
+
+    /* return the address of the given offset within an object file's input section: its run address
+       if the section is loaded, or its offset within its output section if it holds debugging information.
+       call arrange() before this */
+    pub fn section_address(&self, identifier: &FileIdentifier, index: SectionIndex, offset: u64) -> Option<u64>
+    {
+        match self.find_section(identifier, index)
+        {
+            Some(section_idx) => Some(self.address_of(section_idx, offset)),
+            None => self.debug_offsets.get(&(identifier.clone(), index)).map(|base| base + offset)
+        }
+    }
+
+    /* return the contents of the given debugging information section with relocations applied.
+       call arrange() before this */
+    pub fn debug_section_data(&self, output: &DebugSection, config: &Config, manifest: &Manifest) -> Vec<u8>
+    {
+        let mut data = vec![0; output.size as usize];
+
+        for (identifier, index, offset) in &output.inputs
+        {
+            let contents = relocate::debug_section_data(self, identifier, *index, *offset, config, manifest);
+            data[*offset as usize..*offset as usize + contents.len()].copy_from_slice(&contents);
+        }
+
+        data
+    }
+
+    /* return the index of an object file's input section, if it's included in the executable */
+    pub fn find_section(&self, identifier: &FileIdentifier, index: SectionIndex) -> Option<usize>
+    {
+        self.sections.get_index_of(&ManifestSection { identifier: identifier.clone(), index, parent: 0 })
+    }
+
+    /* return the object file and section index of the given input section */
+    pub fn input_section(&self, section_idx: usize) -> (&FileIdentifier, SectionIndex)
+    {
+        (&self.sections[section_idx].identifier, self.sections[section_idx].index)
+    }
+
+    /* return the address of the given entry in the GOT. call arrange() before this */
+    pub fn got_address(&self, slot: usize) -> u64
+    {
+        match self.synthetic_address(SyntheticSection::Got)
+        {
+            Some(got) => got + slot as u64 * self.address_size(),
+            None => fatal_msg!("Unexpected error: GOT entry {} used without a GOT", slot)
+        }
+    }
+
+    /* return the address of the given linker-generated section, if it's in the executable.
+       call arrange() before this */
+    pub fn synthetic_address(&self, synthetic: SyntheticSection) -> Option<u64>
+    {
+        self.outputs.iter().find(|output| output.synthetic == Some(synthetic)).map(|output| output.base_virt_addr)
+    }
+
+    /* return the TLS template's layout: its address, initialized size, total size, and alignment.
+       call arrange() before this */
+    pub fn tls_template(&self) -> Option<(u64, u64, u64, u64)>
+    {
+        let tls: Vec<&OutputSection> = self.outputs.iter().filter(|output| output.tls).collect();
+        let (first, last) = (tls.first()?, tls.last()?);
+        let initialized = tls.iter().filter(|output| !output.nobits).map(|output| output.base_virt_addr + output.size).max();
+
+        Some((first.base_virt_addr,
+            initialized.map_or(0, |end| end - first.base_virt_addr),
+            last.base_virt_addr + last.size - first.base_virt_addr,
+            tls.iter().map(|output| output.alignment).max().unwrap_or(1)))
+    }
+
+    /* return the offset of a thread-local variable at the given address from the start of
+       the TLS template, and so from the thread pointer tp. call arrange() before this */
+    pub fn tls_offset(&self, address: u64) -> Option<u64>
+    {
+        self.tls_template().map(|(base, _, _, _)| address.wrapping_sub(base))
+    }
+
+    pub fn input_section_count(&self) -> usize { self.sections.len() }
+    pub fn get_got(&self) -> &IndexMap<GotEntry, bool> { &self.got }
+    pub fn get_dynamic_relocs(&self) -> &Vec<DynamicReloc> { &self.dynamic }
+
+    /* return the number of R_RISCV_RELATIVE dynamic relocations a relocatable executable needs */
+    pub fn dynamic_reloc_count(&self) -> u64
+    {
+        (self.got.values().filter(|relative| **relative).count() + self.dynamic.len()) as u64
+    }
+    pub fn get_relaxation(&self, section_idx: usize) -> &SectionRelaxation { &self.relaxations[section_idx] }
+
+    /* return the address of the given symbol definition. call arrange() before this */
+    pub fn resolve(&self, definition: &SymbolDefinition) -> u64
+    {
+        match definition
+        {
+            SymbolDefinition::Absolute(value) => *value,
+            SymbolDefinition::Section(section_idx, offset) => self.address_of(*section_idx, *offset)
+        }
+    }
+
+    /* return the name of the symbol an object file's undefined reference to the given name resolves to.
+       for each symbol wrapped with --wrap, eg: foo, references to foo go to __wrap_foo, and references
+       to __real_foo go to the original foo */
+    pub fn reference_name(&self, name: &str) -> String
+    {
+        if self.wrapped.iter().any(|wrapped| wrapped == name)
+        {
+            return format!("__wrap_{}", name);
+        }
+
+        match name.strip_prefix("__real_")
+        {
+            Some(real) if self.wrapped.iter().any(|wrapped| wrapped == real) => String::from(real),
+            _ => String::from(name)
+        }
+    }
+
+    /* return the address of the given global symbol, if it's defined. call arrange() before this */
+    pub fn symbol_address(&self, name: &str) -> Option<u64>
+    {
+        self.symbols.get(name).map(|symbol| self.resolve(&symbol.definition))
+    }
+
+    /* return the index of the output section containing the given symbol definition, if any */
+    pub fn output_section_of(&self, definition: &SymbolDefinition) -> Option<usize>
+    {
+        match definition
+        {
+            SymbolDefinition::Absolute(_) => None,
+            SymbolDefinition::Section(section_idx, _) =>
+            {
+                let ordered_idx = self.placement[*section_idx];
+                self.outputs.iter().position(|output| output.ordered.contains(&ordered_idx))
+            }
+        }
+    }
+
+    pub fn get_output_sections(&self) -> &Vec<OutputSection> { &self.outputs }
+    pub fn get_debug_sections(&self) -> &Vec<DebugSection> { &self.debug }
+    pub fn get_symbols(&self) -> &IndexMap<String, GlobalSymbol> { &self.symbols }
+    pub fn get_local_symbols(&self) -> &Vec<(String, GlobalSymbol)> { &self.locals }
+    pub fn get_e_flags(&self) -> object::FileFlags { self.e_flags }
+    pub fn get_attributes(&self) -> &Option<Attributes> { &self.attributes }
+    pub fn is_64(&self) -> bool { self.is_64 }
+
+    /* return the size in bytes of an address in the executable: 8 for RV64, 4 for RV32 */
+    pub fn address_size(&self) -> u64 { if self.is_64 { 8 } else { 4 } }
+}
+
+/* pick the standard section for an orphan input section with the given name, ELF flags, and kind.
+   thread-local data goes in tdata or tbss, small data in sdata or sbss, executable code in text,
+   writeable data in data or bss, and everything else in rodata */
+fn orphan_parent(name: &str, sh_flags: u64, kind: object::SectionKind) -> usize
+{
+    let parent = if sh_flags & object::elf::SHF_TLS as u64 != 0
+    {
+        match kind
+        {
+            object::SectionKind::UninitializedTls => "tbss",
+            _ => "tdata"
+        }
+    }
+    else if name.starts_with(".srodata") || name.starts_with(".sdata")
+    {
+        "sdata"
+    }
+    else if name.starts_with(".sbss") || name.starts_with(".scommon")
+    {
+        "sbss"
+    }
+    else if sh_flags & object::elf::SHF_EXECINSTR as u64 != 0
+    {
+        "text"
+    }
+    else if sh_flags & object::elf::SHF_WRITE as u64 != 0
+    {
+        match kind
+        {
+            object::SectionKind::UninitializedData => "bss",
+            _ => "data"
+        }
+    }
+    else
+    {
+        "rodata"
+    };
+
+    STANDARD_SECTIONS.iter().position(|(name, _)| *name == parent).unwrap()
+}
+
+/* the load and run addresses, and size, of each standard section arranged so far,
+   for looking up in configuration file expressions */
+struct PlacedSections
+{
+    sections: HashMap<String, (u64, u64, u64)>
+}
+
+impl Lookup for PlacedSections
+{
+    fn function(&self, function: &str, name: &str) -> Result<u64, String>
+    {
+        let (phys_addr, virt_addr, size) = match self.sections.get(name.trim_start_matches('.'))
+        {
+            Some(placed) => *placed,
+            None => return Err(format!("Section {} isn't a standard section placed before this one", name))
+        };
+
+        match function
+        {
+            "ADDR" => Ok(virt_addr),
+            "LOADADDR" => Ok(phys_addr),
+            "SIZEOF" => Ok(size),
+            _ => Err(format!("Unrecognized function {}()", function))
+        }
+    }
+}
+
+/* look up sections and symbols for the expressions giving symbols their values in the config.
+   symbols defined by the config are evaluated as they're needed, so they can refer to each other */
+struct AssignmentLookup<'a>
+{
+    collection: &'a Collection,
+    placed: &'a PlacedSections,
+    evaluating: RefCell<Vec<String>>    /* config symbols being evaluated, to catch circular definitions */
+}
+
+impl Lookup for AssignmentLookup<'_>
+{
+    fn function(&self, function: &str, name: &str) -> Result<u64, String>
+    {
+        self.placed.function(function, name)
+    }
+
+    fn symbol(&self, name: &str) -> Result<u64, String>
+    {
+        let value = match self.collection.assignments.iter().find(|(assigned, _)| assigned == name)
+        {
+            Some((_, value)) => value,
+            None => return self.collection.symbol_address(name).ok_or(format!("Symbol {} isn't defined", name))
+        };
+
+        if self.evaluating.borrow().iter().any(|evaluating| evaluating == name)
+        {
+            return Err(format!("Symbol {} is defined in terms of itself", name));
+        }
+
+        self.evaluating.borrow_mut().push(String::from(name));
+        let result = value.try_evaluate_with(self);
+        self.evaluating.borrow_mut().pop();
+        result
+    }
+}
+
+/* find the memory region, if any, a standard section should be placed in, and check
+   the region has the attributes needed by the section */
+fn lookup_region<'a>(config: &'a Config, section_name: &str, region_name: &'a Option<String>, attributes: &str) -> Option<(&'a String, &'a Region)>
+{
+    let region_name = match region_name
+    {
+        Some(region_name) => region_name,
+        None => return None
+    };
+
+    let region = match config.get_region(region_name)
+    {
+        Some(region) => region,
+        None => fatal_msg!("Section .{} assigned to undefined memory region {}", section_name, region_name)
+    };
+
+    for attribute in attributes.chars()
+    {
+        if !region.allows(region_name, attribute)
+        {
+            fatal_msg!("Section .{} can't be placed in memory region {}: region lacks the '{}' attribute",
+                section_name, region_name, attribute);
+        }
+    }
+
+    Some((region_name, region))
+}
+
+/* bail out if the given end address of a section lies beyond the end of its memory region */
+fn check_region_overflow(section_name: &str, region_name: &String, region: &Region, end_addr: u64)
+{
+    let region_end = region.get_origin(region_name) as u128 + region.get_length(region_name) as u128;
+    if end_addr as u128 > region_end
+    {
+        fatal_msg!("Section .{} overflows memory region {} by {} bytes",
+            section_name, region_name, end_addr as u128 - region_end);
+    }
+}
+
+/* gather up the global symbols defined in the given sections */
+fn gather_symbols(sections: &IndexSet<ManifestSection>, manifest: &Manifest) -> IndexMap<String, GlobalSymbol>
+{
+    let mut symbols: IndexMap<String, GlobalSymbol> = IndexMap::new();
+
+    /* map each object's sections to their index in the gathered sections */
+    let mut lookup = HashMap::new();
+    for (section_idx, section) in sections.iter().enumerate()
+    {
+        lookup.insert((section.identifier.clone(), section.index), section_idx);
+    }
+
+    for (obj_name, mapping) in manifest.raw_objects()
+    {
+        let parsed = manifest::parse(mapping);
+        for symbol in parsed.symbols()
+        {
+            if !symbol.is_global() || symbol.is_undefined()
+            {
+                continue;
+            }
+
+            let definition = match symbol.section()
+            {
+                object::SymbolSection::Absolute => SymbolDefinition::Absolute(symbol.address()),
+                object::SymbolSection::Section(index) => match lookup.get(&(obj_name.clone(), index))
+                {
+                    Some(section_idx) => SymbolDefinition::Section(*section_idx, symbol.address()),
+                    None => continue /* symbol is in a section we're not including */
+                },
+                _ => continue
+            };
+
+            let name = match symbol.name()
+            {
+                Ok(name) => name,
+                Err(reason) => fatal_msg!("Can't read symbol name in {}: {}", obj_name.to_str().unwrap(), reason)
+            };
+
+            /* strong definitions replace weak ones, and two strong definitions clash */
+            if let Some(existing) = symbols.get(name)
+            {
+                match (existing.weak, symbol.is_weak())
+                {
+                    (false, false) => fatal_msg!("Symbol {} defined in {} is already defined elsewhere",
+                                        name, obj_name.to_str().unwrap()),
+                    (false, true) | (true, true) => continue,
+                    (true, false) => ()
+                }
+            }
+
+            symbols.insert(String::from(name), GlobalSymbol
+            {
+                definition,
+                kind: symbol.kind(),
+                size: symbol.size(),
+                weak: symbol.is_weak()
+            });
+        }
+    }
+
+    symbols
+}
+
+/* gather up the symbols local to each object file that are defined in the given sections or are absolute,
+   skipping section and file symbols. these only go in the executable's symbol table: they're not
+   used to resolve references between objects */
+fn gather_local_symbols(sections: &IndexSet<ManifestSection>, manifest: &Manifest) -> Vec<(String, GlobalSymbol)>
+{
+    let mut locals = Vec::new();
+
+    for (obj_name, mapping) in manifest.raw_objects()
+    {
+        let parsed = manifest::parse(mapping);
+        for symbol in parsed.symbols()
+        {
+            if !symbol.is_local() || symbol.is_undefined()
+            {
+                continue;
+            }
+
+            match symbol.kind()
+            {
+                SymbolKind::Section | SymbolKind::File | SymbolKind::Null => continue,
+                _ => ()
+            }
+
+            let definition = match symbol.section()
+            {
+                object::SymbolSection::Absolute => SymbolDefinition::Absolute(symbol.address()),
+                object::SymbolSection::Section(index) =>
+                    match sections.get_index_of(&ManifestSection { identifier: obj_name.clone(), index, parent: 0 })
+                {
+                    Some(section_idx) => SymbolDefinition::Section(section_idx, symbol.address()),
+                    None => continue /* symbol is in a section we're not including */
+                },
+                _ => continue
+            };
+
+            match symbol.name()
+            {
+                Ok(name) if !name.is_empty() => locals.push((String::from(name), GlobalSymbol
+                {
+                    definition,
+                    kind: symbol.kind(),
+                    size: symbol.size(),
+                    weak: false
+                })),
+                Ok(_) => (),
+                Err(reason) => fatal_msg!("Can't read symbol name in {}: {}", obj_name.to_str().unwrap(), reason)
+            }
+        }
+    }
+
+    locals
+}
+
+/* gather up the object files' debugging information sections, concatenating those with the same name,
+   in the order they're first found, into non-allocated output sections */
+fn gather_debug_sections(manifest: &Manifest) -> Vec<DebugSection>
+{
+    let mut debug: Vec<DebugSection> = Vec::new();
+
+    for (obj_name, mapping) in manifest.raw_objects()
+    {
+        let parsed = manifest::parse(mapping);
+        for section in parsed.sections()
+        {
+            let sh_flags = match section.flags()
+            {
+                object::SectionFlags::Elf { sh_flags } => sh_flags,
+                _ => 0
+            };
+            let name = section.name().unwrap_or("");
+            if !name.starts_with(DEBUG_SECTION_PREFIX) || sh_flags & object::elf::SHF_ALLOC as u64 != 0
+            {
+                continue;
+            }
+
+            /* relocations apply to the uncompressed contents, so there's no way to relocate compressed ones */
+            if sh_flags & object::elf::SHF_COMPRESSED as u64 != 0
+            {
+                fatal_msg!("Can't include compressed debugging section {} in {}: build it with uncompressed debugging information",
+                    name, obj_name.to_str().unwrap());
+            }
+
+            let output_idx = match debug.iter().position(|output| output.name == name)
+            {
+                Some(output_idx) => output_idx,
+                None =>
+                {
+                    debug.push(DebugSection { name: String::from(name), alignment: 1, size: 0, inputs: Vec::new() });
+                    debug.len() - 1
+                }
+            };
+
+            let output = &mut debug[output_idx];
+            let alignment = std::cmp::max(section.align(), 1);
+            let offset = align_to(output.size, alignment);
+            output.inputs.push((obj_name.to_path_buf(), section.index(), offset));
+            output.size = offset + section.size();
+            output.alignment = std::cmp::max(output.alignment, alignment);
+        }
+    }
+
+    debug
+}
+
+/* merge the .riscv.attributes sections of the object files providing sections to the executable.
+   if the config gives a target ISA, reject objects that need extensions the target doesn't have,
+   going by their attributes' ISA strings and whether they use compressed instructions */
+fn gather_attributes(config: &Config, sections: &IndexSet<ManifestSection>, manifest: &Manifest) -> Option<Attributes>
+{
+    let mut objects: Vec<(&FileIdentifier, Attributes)> = Vec::new();
+    let target = config.get_output().get_isa();
+    let mut unsupported = 0;
+    let mut visited: Vec<&FileIdentifier> = Vec::new();
+
+    for section in sections
+    {
+        if visited.contains(&&section.identifier)
+        {
+            continue;
+        }
+        visited.push(&section.identifier);
+
+        let parsed = match manifest.get(&section.identifier)
+        {
+            Some(mapping) => manifest::parse(mapping),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?}", section.identifier)
+        };
+
+        let object_attributes = match parsed.section_by_name(attributes::SECTION_NAME).map(|section| section.data())
+        {
+            Some(Ok(data)) => Some(Attributes::parse(data, &section.identifier)),
+            Some(Err(reason)) => fatal_msg!("Can't read {} section in {}: {}", attributes::SECTION_NAME, section.identifier.to_str().unwrap(), reason),
+            None => None
+        };
+
+        if let Some(target) = &target
+        {
+            let mut missing = match object_attributes.as_ref().and_then(|attributes| attributes.get_arch().as_ref())
+            {
+                Some(arch) if arch.get_xlen() != target.get_xlen() =>
+                {
+                    eprintln!("Object {} is built for RV{} but the target ISA {} is RV{}",
+                        section.identifier.to_str().unwrap(), arch.get_xlen(), target, target.get_xlen());
+                    unsupported += 1;
+                    Vec::new()
+                },
+                Some(arch) => arch.missing_from(target),
+                None => Vec::new()
+            };
+
+            let uses_rvc = match parsed.flags()
+            {
+                object::FileFlags::Elf { e_flags } => e_flags & EF_RISCV_RVC != 0,
+                _ => false
+            };
+            if uses_rvc && !target.has("c") && !missing.iter().any(|extension| extension == "c")
+            {
+                missing.push(String::from("c"));
+            }
+
+            if !missing.is_empty()
+            {
+                eprintln!("Object {} requires extension(s) {} missing from the target ISA {}",
+                    section.identifier.to_str().unwrap(), missing.join(", "), target);
+                unsupported += 1;
+            }
+        }
+
+        if let Some(object_attributes) = object_attributes
+        {
+            objects.push((&section.identifier, object_attributes));
+        }
+    }
+
+    if unsupported > 0
+    {
+        fatal_msg!("Found {} object file(s) not supported by the target ISA", unsupported);
+    }
+
+    attributes::merge(&objects)
+}
+
+/* read in the files the config embeds in the executable, grouping them by output section in the
+   order the sections are first given. each file is marked by symbols named after its path, with
+   each character that isn't a letter or digit replaced with _, as objcopy does, eg: guest.bin
+   is marked by _binary_guest_bin_start, _binary_guest_bin_end, and _binary_guest_bin_size */
+fn gather_embedded(config: &Config) -> Vec<EmbeddedSection>
+{
+    let mut embedded: Vec<EmbeddedSection> = Vec::new();
+
+    for embed in config.get_embeds()
+    {
+        let name = embed.get_section();
+        if STANDARD_SECTIONS.iter().any(|(standard, _)| name.trim_start_matches('.') == *standard) ||
+            SYNTHETIC_SECTIONS.iter().any(|(_, synthetic, _)| name == synthetic)
+        {
+            fatal_msg!("Can't embed {} in section {}: the section is generated by the linker", embed.get_file(), name);
+        }
+
+        let contents = match std::fs::read(embed.get_file())
+        {
+            Ok(contents) => contents,
+            Err(reason) => fatal_msg!("Can't read file {} to embed: {}", embed.get_file(), reason)
+        };
+
+        let section = match embedded.iter().position(|section| section.name == *name)
+        {
+            Some(idx) => &mut embedded[idx],
+            None =>
+            {
+                embedded.push(EmbeddedSection { name: name.clone(), files: Vec::new(), alignment: 1, size: 0 });
+                embedded.last_mut().unwrap()
+            }
+        };
+
+        let alignment = embed.get_alignment();
+        let offset = align_to(section.size, alignment);
+        section.alignment = std::cmp::max(section.alignment, alignment);
+        section.size = offset + contents.len() as u64;
+        section.files.push(EmbeddedFile
+        {
+            symbol: format!("_binary_{}", embed.get_file().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>()),
+            contents, offset
+        });
+    }
+
+    embedded
+}
+
+/* align the given address up to the next power-of-two alignment, if necessary */
+fn align_to(address: u64, alignment: u64) -> u64
+{
+    let align_down = address & !(alignment - 1);
+    
+    if align_down == address
+    {
+        address
+    }
+    else
+    {
+        align_down + alignment
+    }
+}
+
+/* define e_flags masks */
+pub const EF_RISCV_RVC: u32 = 0x0001;               /* bit    0 = C ext (compressed instructions) in use */
+const EF_RISCV_FLOAT_ABI_MASK: u32 = 0x0006;        /* bits 1-2 = float ABI level */
+const EF_RISCV_FLOAT_ABI_SOFT: u32 = 0x0000;
+const EF_RISCV_FLOAT_ABI_SINGLE: u32 = 0x0002;
+const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x0004;
+const EF_RISCV_RVE: u32 = 0x0008;                   /* bit    3 = RISC-V EABI in use */
+const EF_RISCV_TSO: u32 = 0x0010;                   /* bit    4 = RVTSO memory consistency model required */
+
+/* summarize usage flag bitmask in e_flags */
+const EF_USAGE_FLAGS: u32 = EF_RISCV_RVC | EF_RISCV_TSO;
+
+/* return the name of the float ABI in the given e_flags */
+fn float_abi_name(e_flags: u32) -> &'static str
+{
+    match e_flags & EF_RISCV_FLOAT_ABI_MASK
+    {
+        EF_RISCV_FLOAT_ABI_SOFT => "soft-float",
+        EF_RISCV_FLOAT_ABI_SINGLE => "single-float",
+        EF_RISCV_FLOAT_ABI_DOUBLE => "double-float",
+        _ => "quad-float"
+    }
+}
+
+/* decide whether to generate an RV64 or RV32 executable. the config's target ISA decides, if
+   one is given, otherwise the first object file providing sections does. object files built
+   for the other width are reported and the link fails. returns true for RV64 */
+fn select_width(config: &Config, sections: &IndexSet<ManifestSection>, manifest: &Manifest) -> bool
+{
+    let mut selected = match config.get_output().get_isa().map(|isa| isa.get_xlen())
+    {
+        Some(64) => Some((true, String::from("the configuration file's output.isa"))),
+        Some(32) => Some((false, String::from("the configuration file's output.isa"))),
+        Some(xlen) => fatal_msg!("Can't generate RV{} executables: only RV32 and RV64 are supported", xlen),
+        None => None
+    };
+
+    let width = |is_64: bool| if is_64 { "RV64" } else { "RV32" };
+    let mut visited: Vec<&FileIdentifier> = Vec::new();
+    let mut mismatches = 0;
+
+    for section in sections
+    {
+        if visited.contains(&&section.identifier)
+        {
+            continue;
+        }
+        visited.push(&section.identifier);
+
+        let is_64 = match manifest.get(&section.identifier)
+        {
+            Some(mapping) => manifest::parse(mapping).is_64(),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?}", section.identifier)
+        };
+
+        match &selected
+        {
+            None => selected = Some((is_64, format!("object {}", section.identifier.to_str().unwrap()))),
+            Some((selected_64, source)) => if *selected_64 != is_64
+            {
+                eprintln!("Object {} is built for {} but {} is {}",
+                    section.identifier.to_str().unwrap(), width(is_64), source, width(*selected_64));
+                mismatches += 1;
+            }
+        }
+    }
+
+    if mismatches > 0
+    {
+        fatal_msg!("Found {} object file(s) built for the wrong base width", mismatches);
+    }
+
+    match selected
+    {
+        Some((is_64, _)) => is_64,
+        None => true
+    }
+}
+
+/* work out the executable's e_flags from the e_flags of the object files providing its sections.
+   these flags are processor architecture (RISC-V) dependent and are
+   defined here: https://github.com/riscv-non-isa/riscv-elf-psabi-doc
+
+   the compressed instruction and RVTSO memory model usage flags are combined. the float ABI
+   and RVE (embedded) ABI flags must match across all objects, as code built for different
+   ABIs can't call each other. objects that differ are reported and the link fails, unless
+   the objects contain no code or data and the config says to ignore the flags of such objects */
+fn merge_e_flags(config: &Config, sections: &IndexSet<ManifestSection>, manifest: &Manifest) -> object::FileFlags
+{
+    /* note each object's flags, and whether it provides any code or data */
+    let mut objects: IndexMap<&FileIdentifier, (u32, bool)> = IndexMap::new();
+    for section in sections
+    {
+        let parsed = match manifest.get(&section.identifier)
+        {
+            Some(mapping) => manifest::parse(mapping),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?}", section.identifier)
+        };
+        let obj_flags = match parsed.flags()
+        {
+            object::FileFlags::None => 0,
+            object::FileFlags::Elf { e_flags } => e_flags,
+            other => fatal_msg!("Unexpected error: unrecognized object flags {:?}", other)
+        };
+        let size = parsed.section_by_index(section.index).map_or(0, |section| section.size());
+
+        let object = objects.entry(&section.identifier).or_insert((obj_flags, false));
+        object.1 = object.1 || size > 0;
+    }
+
+    let ignore_empty = config.get_output().ignore_empty_object_abi();
+    let abi_flags = EF_RISCV_FLOAT_ABI_MASK | EF_RISCV_RVE;
+    let mut first: Option<(&FileIdentifier, u32)> = None;
+    let mut elf_flags = 0;
+    let mut mismatches = 0;
+
+    for (identifier, (obj_flags, has_contents)) in &objects
+    {
+        if !*has_contents && ignore_empty
+        {
+            continue;
+        }
+
+        /* set bits for compressed instruction, RVTSO memory model, etc usage */
+        elf_flags |= obj_flags & EF_USAGE_FLAGS;
+
+        /* the first object sets the ABI, and the rest must follow it */
+        let (first_identifier, first_flags) = match first
+        {
+            Some(first) => first,
+            None =>
+            {
+                first = Some((identifier, *obj_flags));
+                elf_flags |= obj_flags & abi_flags;
+                continue;
+            }
+        };
+
+        let hint = match has_contents
+        {
+            true => "",
+            false => " (it contains no code or data, so set ignore_empty_object_abi = true to ignore its flags)"
+        };
+
+        if (obj_flags ^ first_flags) & EF_RISCV_FLOAT_ABI_MASK != 0
+        {
+            eprintln!("Object {} uses the {} ABI but {} uses the {} ABI{}",
+                identifier.to_str().unwrap(), float_abi_name(*obj_flags),
+                first_identifier.to_str().unwrap(), float_abi_name(first_flags), hint);
+            mismatches += 1;
+        }
+
+        if (obj_flags ^ first_flags) & EF_RISCV_RVE != 0
+        {
+            let rve = |flags: u32| if flags & EF_RISCV_RVE != 0 { "the RVE" } else { "the RVI" };
+            eprintln!("Object {} uses {} ABI but {} uses {} ABI{}",
+                identifier.to_str().unwrap(), rve(*obj_flags),
+                first_identifier.to_str().unwrap(), rve(first_flags), hint);
+            mismatches += 1;
+        }
+    }
+
+    if mismatches > 0
+    {
+        fatal_msg!("Found {} ABI mismatch(es) between object files", mismatches);
+    }
+
+    /* return the merged flag bits */
+    object::FileFlags::Elf { e_flags: elf_flags }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn input_section(identifier: &str, index: usize, parent: usize) -> ManifestSection
+    {
+        ManifestSection { identifier: FileIdentifier::from(identifier), index: SectionIndex(index), parent }
+    }
+
+    #[test]
+    fn input_sections_identified_by_object_and_index()
+    {
+        let mut sections = IndexSet::new();
+
+        /* sections with the same name, and so the same index, in different objects are different input sections */
+        assert_eq!(sections.insert_full(input_section("a.o", 1, 0)), (0, true));
+        assert_eq!(sections.insert_full(input_section("b.o", 1, 0)), (1, true));
+        assert_eq!(sections.insert_full(input_section("a.o", 2, 0)), (2, true));
+
+        /* an input section matched again for another standard section is the one already
+           placed, and stays in the standard section that claimed it first */
+        assert_eq!(sections.insert_full(input_section("a.o", 1, 3)), (0, false));
+        assert_eq!(sections[0].parent, 0);
+        assert_eq!(sections.len(), 3);
+    }
+}
@@ -1,6 +1,6 @@
 /* itsylinker
  * 
- * Minimalist linker that generates 64-bit RISC-V (RV64I) ELF files
+ * Minimalist linker that generates 32 and 64-bit RISC-V (RV32I, RV64I) ELF files
  *
  * Syntax: itsylinker [options] objects...
  * 
@@ -9,6 +9,13 @@
  * -L <path>        Add <path> to the list of paths that will be searched for the given files to link
  * -o <output>      Generate the linked ELF executable at <output> or a.out in the current working directory if not specified
  * -T <config>      Read linker settings from configuration file <config>
+ * -u <symbol>      Warn if <symbol> isn't defined, also --undefined=<symbol>
+ * --defsym=<symbol>=<expression>  Define <symbol> as the value of <expression>
+ * --oformat <format>  Generate the output as elf, binary, ihex, or srec
+ * --wrap=<symbol>  Redirect undefined references to <symbol> to __wrap_<symbol>, and to __real_<symbol> to <symbol>
+ * -s               Leave the symbol table and debugging information out of the ELF executable, also --strip-all
+ * -S               Leave debugging information out of the ELF executable, also --strip-debug
+ * -X               Leave temporary .L symbols out of the symbol table, also --discard-locals
  * --start-group    Mark the start of a group of files in which to resolve all possible references
  * --end-group      Mark the end of a group created by --start-group
  * 
@@ -51,9 +58,13 @@ mod debug;     /* provides fatal_msg() macro */
 mod cmd;       /* command-line parser */
 mod context;   /* describe the linking context */
 mod config;    /* configuration file parser */
+mod expr;      /* evaluate numeric expressions in the config file */
 mod search;    /* find files for the linking process */
 mod gather;    /* gather sections, symbols, and relocations */
+mod relocate;  /* apply relocations and relax code */
+mod attributes; /* parse and merge RISC-V attributes */
 mod output;    /* generate the ELF executable */
+mod records;   /* generate Intel HEX and S-record images */
 mod manifest;  /* manage the files to process */
 
 /* here's the process flow of the linker:
@@ -61,7 +72,8 @@ mod manifest;  /* manage the files to process */
        this context identifies the work that needs to be done.
     2. iterate over the files to link, gathering their section headers,
        global symbols, and relocations. cache the file contents, too.
-    3. assign sequential base addresses for the sections.
+    3. assign sequential base addresses for the sections, relaxing code
+       and reassigning the addresses until the code can't shrink any further.
     4. write out the sections, symbols and relocations as an executable.
 */
 
@@ -9,14 +9,15 @@ use std::fs::File;
 use object::Object;
 use std::path::{ Path, PathBuf };
 use memmap2::{ MmapOptions, Mmap };
-use std::collections::HashMap;
+use indexmap::map::IndexMap;
 
 pub type FileIdentifier = PathBuf;
 
-/* a manifest is a map of file identifiers to their placement in memory */
+/* a manifest is a map of file identifiers to their placement in memory.
+   files are kept in the order they were added, which is the order they were given to the linker */
 pub struct Manifest
 {
-    data: HashMap<FileIdentifier, Mmap>
+    data: IndexMap<FileIdentifier, Mmap>
 }
 
 /* manage the manifest of files */
@@ -27,7 +28,7 @@ impl Manifest
     {
         Manifest
         {
-            data: HashMap::new()
+            data: IndexMap::new()
         }
     }
 
@@ -85,8 +86,8 @@ impl Manifest
             fatal_msg!("Unsupported binary format {}: {:?}", psuedo_path.to_str().unwrap(), object.format())
         );
 
-        /* only accept 64-bit RISC-V object files */
-        (object.architecture() != object::Architecture::Riscv64).then(||
+        /* only accept 32 and 64-bit RISC-V object files */
+        (object.architecture() != object::Architecture::Riscv64 && object.architecture() != object::Architecture::Riscv32).then(||
             fatal_msg!("Can't parse non-RISC-V object file {}, type {:?}",
             psuedo_path.to_str().unwrap(), object.architecture()));
 
@@ -155,7 +156,7 @@ impl Manifest
     }
 
     /* iterate over all the memory-mapped object files in the manifest */
-    pub fn raw_objects(&self) -> std::collections::hash_map::Iter<PathBuf, Mmap>
+    pub fn raw_objects(&self) -> indexmap::map::Iter<PathBuf, Mmap>
     {
         self.data.iter()
     }
@@ -1,19 +1,24 @@
 /* Output an executable from collection of object files
- * 
+ *
  * use gather.rs to produce a rough draft of
  * the executable and then organize everything needed
  * into a final executable
- * 
+ *
  * (c) Chris Williams, 2021.
  *
  * See LICENSE for usage and copying.
  */
 
-use super::gather;
+use super::gather::{ self, SectionSegment, SyntheticSection };
 use super::context::Context;
+use super::config::{ Config, ExecutablePlacement, OutputFormat, Strip };
+use super::manifest::Manifest;
+use super::attributes;
+use super::records::{ self, Chunk };
 
+use object::elf;
 use object::endian::Endianness;
-use object::write::elf::Writer;
+use object::write::elf::{ Writer, FileHeader, ProgramHeader, SectionHeader, Sym };
 
 /* produce an ELF executable from the supplied configuration and command-line paramters */
 pub fn write(cxt: &Context)
@@ -29,15 +34,475 @@ pub fn write(cxt: &Context)
     sections.merge();
     sections.arrange(config, &manifest);
 
-    /* start generating the executable */
+    /* generate the executable in the requested format, and any other files listed in the config,
+       all from the one layout so that they're consistent with each other */
+    let output_file = cxt.get_output_file();
+    let mut filenames = vec![&output_file];
+    for artifact in config.get_artifacts()
+    {
+        if filenames.contains(&artifact.get_file())
+        {
+            fatal_msg!("Can't generate {} more than once from the same link", artifact.get_file());
+        }
+        filenames.push(artifact.get_file());
+    }
+
+    let image = Image { fill: config.get_output().get_fill_byte(), narrow_srec: config.get_output().narrow_srec() };
+    let symbols = SymbolTable { strip: cxt.get_strip(), discard_locals: cxt.discard_locals() };
+    write_file(&output_file, generate(cxt.get_output_format(), &image, &symbols, config, &sections, &manifest));
+
+    for artifact in config.get_artifacts()
+    {
+        let image = Image { fill: artifact.get_fill_byte(config.get_output()), narrow_srec: artifact.narrow_srec(config.get_output()) };
+        let symbols = SymbolTable { strip: artifact.get_strip(cxt.get_strip()), ..symbols };
+        write_file(artifact.get_file(), generate(artifact.get_format(), &image, &symbols, config, &sections, &manifest));
+    }
+}
+
+/* describe how to generate memory images: the byte to fill gaps in flat images with,
+   and whether S-record images use the narrowest records that fit */
+struct Image
+{
+    fill: u8,
+    narrow_srec: bool
+}
+
+/* describe what to strip from an ELF executable, and whether to leave out temporary .L symbols */
+#[derive(Clone, Copy)]
+struct SymbolTable
+{
+    strip: Strip,
+    discard_locals: bool
+}
+
+/* generate the arranged sections in the given format, using the given settings for memory images
+   and the given symbol table settings for ELF executables */
+fn generate(format: OutputFormat, image: &Image, symbols: &SymbolTable, config: &Config, sections: &gather::Collection, manifest: &Manifest) -> Vec<u8>
+{
+    match format
+    {
+        OutputFormat::Elf => write_elf(symbols, config, sections, manifest),
+        OutputFormat::Binary => write_binary(image.fill, config, sections, manifest),
+        OutputFormat::Ihex => records::to_ihex(&loaded_chunks(config, sections, manifest), entry_load_address(config, sections)),
+        OutputFormat::Srec => records::to_srec(&loaded_chunks(config, sections, manifest), entry_load_address(config, sections), image.narrow_srec)
+    }
+}
+
+/* write out a generated file to storage */
+fn write_file(filename: &String, contents: Vec<u8>)
+{
+    if let Err(reason) = std::fs::write(filename, contents)
+    {
+        fatal_msg!("Unable to create executable file {}: {}", filename, reason);
+    }
+}
+
+/* find the entry point, falling back to the start of the first section like ld does */
+fn entry_address(config: &Config, sections: &gather::Collection) -> u64
+{
+    let entry_symbol = config.get_output().get_entry_symbol();
+    match sections.symbol_address(entry_symbol)
+    {
+        Some(addr) => addr,
+        None =>
+        {
+            let fallback = sections.get_output_sections().first().map_or(0, |output| output.base_virt_addr);
+            eprintln!("Warning: Can't find entry symbol {}, defaulting to 0x{:x}", entry_symbol, fallback);
+            fallback
+        }
+    }
+}
+
+/* find the entry point's load address, for images whose sections are at their load addresses.
+   eg: a kernel that runs from 0xffffffff80000000 but is loaded at 0x80000000 starts at 0x80000000 */
+fn entry_load_address(config: &Config, sections: &gather::Collection) -> u64
+{
+    let entry = entry_address(config, sections);
+    match sections.get_output_sections().iter()
+        .find(|output| !output.tls && entry >= output.base_virt_addr && entry < output.base_virt_addr + output.size)
+    {
+        Some(output) => entry - output.base_virt_addr + output.base_phys_addr,
+        None => entry
+    }
+}
+
+/* return the contents of the loadable sections that take up space, at their load addresses */
+fn loaded_chunks(config: &Config, sections: &gather::Collection, manifest: &Manifest) -> Vec<Chunk>
+{
+    sections.get_output_sections().iter()
+        .filter(|output| !output.nobits && output.size > 0)
+        .map(|output| Chunk { address: output.base_phys_addr, data: sections.output_section_data(output, config, manifest) })
+        .collect()
+}
+
+/* generate a flat memory image of the arranged sections, starting from the lowest load address.
+   padding between sections is filled with the given fill byte. uninitialized data takes up
+   no space where the sections are loaded, so it isn't included unless other sections follow it,
+   in which case it's zeroed like the loader would */
+fn write_binary(fill: u8, config: &Config, sections: &gather::Collection, manifest: &Manifest) -> Vec<u8>
+{
+    let chunks = loaded_chunks(config, sections, manifest);
+    let base = match chunks.iter().map(|chunk| chunk.address).min()
+    {
+        Some(base) => base,
+        None => return Vec::new()
+    };
+    let end = chunks.iter().map(|chunk| chunk.address + chunk.data.len() as u64).max().unwrap_or(base);
+
+    let mut image = vec![fill; (end - base) as usize];
+    for output in sections.get_output_sections().iter().filter(|output| output.nobits && !output.tls)
+    {
+        let start = output.base_phys_addr.clamp(base, end);
+        let finish = (output.base_phys_addr + output.size).clamp(base, end);
+        image[(start - base) as usize..(finish - base) as usize].fill(0);
+    }
+
+    for chunk in chunks
+    {
+        let offset = (chunk.address - base) as usize;
+        image[offset..offset + chunk.data.len()].copy_from_slice(&chunk.data);
+    }
+
+    image
+}
+
+/* generate an ELF executable in memory from the arranged sections, using ELFCLASS64 for RV64
+   executables and ELFCLASS32 for RV32 executables. each output section
+   gets its own loadable segment, using the section's load address as the segment's
+   physical address and its run address as the segment's virtual address. if there are
+   any thread-local sections, a TLS segment describes the template they form. uninitialized
+   thread-local data isn't loaded, so it gets no loadable segment, just space in the TLS segment. the merged
+   RISC-V attributes, if any, are written to a non-loadable section with its own segment.
+   debugging information sections follow, without segments, unless debugging information is stripped.
+   the symbol table holds the symbols local to each object file followed by the global symbols,
+   and is left out altogether if everything is stripped */
+fn write_elf(table: &SymbolTable, config: &Config, sections: &gather::Collection, manifest: &Manifest) -> Vec<u8>
+{
+    let outputs = sections.get_output_sections();
+    let has_symtab = table.strip != Strip::All;
+    let debug: &[gather::DebugSection] = match table.strip
+    {
+        Strip::None => sections.get_debug_sections(),
+        _ => &[]
+    };
+    let tls_template = sections.tls_template();
+    let attributes = sections.get_attributes().as_ref().map(|attributes| attributes.to_bytes());
+
+    let entry = entry_address(config, sections);
+
     let mut output_buffer = Vec::new();
-    let mut writer = Writer::new(Endianness::Little, true, &mut output_buffer);
+    let mut writer = Writer::new(Endianness::Little, sections.is_64(), &mut output_buffer);
 
+    /* reserve space in the file for the headers and the section contents. keep the
+       section contents aligned in the file as they will be aligned in memory */
     writer.reserve_file_header();
+    let dynamic = outputs.iter().any(|output| output.synthetic == Some(SyntheticSection::Dynamic));
+    writer.reserve_program_headers(outputs.iter().filter(|output| output.occupies_memory()).count() as u32 +
+        if tls_template.is_some() { 1 } else { 0 } +
+        if dynamic { 1 } else { 0 } +
+        if attributes.is_some() { 1 } else { 0 });
 
-    /* and write it all out to an executable in storage */
-    if let Err(reason) = std::fs::write(&cxt.get_output_file(), output_buffer)
+    let mut offsets = Vec::new();
+    for output in outputs
+    {
+        offsets.push(match output.nobits
+        {
+            true => writer.reserved_len(),
+            false => writer.reserve(output.size as usize, output.alignment as usize)
+        });
+    }
+
+    let attributes_offset = attributes.as_ref().map(|attributes| writer.reserve(attributes.len(), 1));
+    let debug_offsets: Vec<usize> = debug.iter().map(|output| writer.reserve(output.size as usize, output.alignment as usize)).collect();
+
+    /* reserve the section headers */
+    writer.reserve_null_section_index();
+    let mut section_names = Vec::new();
+    let mut section_indices = Vec::new();
+    for output in outputs
+    {
+        section_names.push(writer.add_section_name(output.name.as_bytes()));
+        section_indices.push(writer.reserve_section_index());
+    }
+    let attributes_name = attributes.as_ref().map(|_| writer.add_section_name(attributes::SECTION_NAME.as_bytes()));
+    if attributes.is_some()
+    {
+        writer.reserve_section_index();
+    }
+    let mut debug_names = Vec::new();
+    for output in debug
     {
-        fatal_msg!("Unable to create executable file {}: {}", cxt.get_output_file(), reason);
+        debug_names.push(writer.add_section_name(output.name.as_bytes()));
+        writer.reserve_section_index();
     }
-}
\ No newline at end of file
+    if has_symtab
+    {
+        writer.reserve_symtab_section_index();
+        writer.reserve_strtab_section_index();
+    }
+    writer.reserve_shstrtab_section_index();
+
+    /* gather the symbols to write, locals first as ELF requires, with their bindings */
+    let mut symbols = Vec::new();
+    if has_symtab
+    {
+        for (name, symbol) in sections.get_local_symbols()
+        {
+            if !table.discard_locals || !name.starts_with(".L")
+            {
+                symbols.push((name, symbol, elf::STB_LOCAL));
+            }
+        }
+        for (name, symbol) in sections.get_symbols()
+        {
+            symbols.push((name, symbol, if symbol.weak { elf::STB_WEAK } else { elf::STB_GLOBAL }));
+        }
+    }
+    let local_count = symbols.iter().filter(|(_, _, binding)| *binding == elf::STB_LOCAL).count();
+
+    /* reserve the symbols, which go in the symbol table after the null symbol */
+    let mut symbol_names = Vec::new();
+    if has_symtab
+    {
+        writer.reserve_null_symbol_index();
+        for (name, symbol, _) in &symbols
+        {
+            symbol_names.push(writer.add_string(name.as_bytes()));
+            writer.reserve_symbol_index(sections.output_section_of(&symbol.definition).map(|idx| section_indices[idx]));
+        }
+
+        writer.reserve_symtab();
+        writer.reserve_strtab();
+    }
+    writer.reserve_shstrtab();
+    writer.reserve_section_headers();
+
+    /* now write everything out in the order it was reserved */
+    if let Err(reason) = writer.write_file_header(&FileHeader
+    {
+        os_abi: elf::ELFOSABI_NONE,
+        abi_version: 0,
+        e_type: match config.get_output().get_placement()
+        {
+            ExecutablePlacement::Relocatable => elf::ET_DYN,
+            ExecutablePlacement::Static(_, _) => elf::ET_EXEC
+        },
+        e_machine: elf::EM_RISCV,
+        e_entry: entry,
+        e_flags: match sections.get_e_flags()
+        {
+            object::FileFlags::Elf { e_flags } => e_flags,
+            _ => 0
+        }
+    })
+    {
+        fatal_msg!("Can't write ELF file header: {}", reason);
+    }
+
+    writer.write_align_program_headers();
+    for (output, offset) in outputs.iter().zip(offsets.iter()).filter(|(output, _)| output.occupies_memory())
+    {
+        writer.write_program_header(&ProgramHeader
+        {
+            p_type: elf::PT_LOAD,
+            p_flags: match output.segment
+            {
+                SectionSegment::LoadableRead => elf::PF_R,
+                SectionSegment::LoadableReadWrite => elf::PF_R | elf::PF_W,
+                SectionSegment::LoadableReadExec => elf::PF_R | elf::PF_X
+            },
+            p_offset: *offset as u64,
+            p_vaddr: output.base_virt_addr,
+            p_paddr: output.base_phys_addr,
+            p_filesz: if output.nobits { 0 } else { output.size },
+            p_memsz: output.size,
+            p_align: output.alignment
+        });
+    }
+
+    let first_tls = outputs.iter().zip(offsets.iter()).find(|(output, _)| output.tls);
+    if let (Some((base, initialized_size, size, alignment)), Some((first, offset))) = (tls_template, first_tls)
+    {
+        writer.write_program_header(&ProgramHeader
+        {
+            p_type: elf::PT_TLS,
+            p_flags: elf::PF_R,
+            p_offset: *offset as u64,
+            p_vaddr: base,
+            p_paddr: first.base_phys_addr,
+            p_filesz: initialized_size,
+            p_memsz: size,
+            p_align: alignment
+        });
+    }
+
+    /* relocatable executables describe their dynamic relocations to the loader */
+    if let Some((output, offset)) = outputs.iter().zip(offsets.iter()).find(|(output, _)| output.synthetic == Some(SyntheticSection::Dynamic))
+    {
+        writer.write_program_header(&ProgramHeader
+        {
+            p_type: elf::PT_DYNAMIC,
+            p_flags: elf::PF_R | elf::PF_W,
+            p_offset: *offset as u64,
+            p_vaddr: output.base_virt_addr,
+            p_paddr: output.base_phys_addr,
+            p_filesz: output.size,
+            p_memsz: output.size,
+            p_align: output.alignment
+        });
+    }
+
+    if let (Some(attributes), Some(offset)) = (&attributes, attributes_offset)
+    {
+        writer.write_program_header(&ProgramHeader
+        {
+            p_type: attributes::PT_RISCV_ATTRIBUTES,
+            p_flags: elf::PF_R,
+            p_offset: offset as u64,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: attributes.len() as u64,
+            p_memsz: 0,
+            p_align: 1
+        });
+    }
+
+    for (output, offset) in outputs.iter().zip(offsets.iter())
+    {
+        if !output.nobits
+        {
+            writer.pad_until(*offset);
+            writer.write(&sections.output_section_data(output, config, manifest));
+        }
+    }
+
+    if let (Some(attributes), Some(offset)) = (&attributes, attributes_offset)
+    {
+        writer.pad_until(offset);
+        writer.write(attributes);
+    }
+
+    for (output, offset) in debug.iter().zip(debug_offsets.iter())
+    {
+        writer.pad_until(*offset);
+        writer.write(&sections.debug_section_data(output, config, manifest));
+    }
+
+    if has_symtab
+    {
+        writer.write_null_symbol();
+    }
+    for ((_, symbol, binding), name) in symbols.iter().zip(symbol_names.iter())
+    {
+        let section = sections.output_section_of(&symbol.definition);
+        writer.write_symbol(&Sym
+        {
+            name: Some(*name),
+            section: section.map(|idx| section_indices[idx]),
+            st_info: (binding << 4) | match symbol.kind
+            {
+                object::SymbolKind::Text => elf::STT_FUNC,
+                object::SymbolKind::Data => elf::STT_OBJECT,
+                object::SymbolKind::Tls => elf::STT_TLS,
+                _ => elf::STT_NOTYPE
+            },
+            st_other: elf::STV_DEFAULT,
+            st_shndx: if section.is_some() { 0 } else { elf::SHN_ABS },
+            st_value: match (symbol.kind, section)
+            {
+                /* thread-local symbols hold their offset within the TLS template */
+                (object::SymbolKind::Tls, Some(_)) => sections.tls_offset(sections.resolve(&symbol.definition)).unwrap_or(0),
+                _ => sections.resolve(&symbol.definition)
+            },
+            st_size: symbol.size
+        });
+    }
+
+    if has_symtab
+    {
+        writer.write_strtab();
+    }
+    writer.write_shstrtab();
+
+    writer.write_null_section_header();
+    for ((output, offset), name) in outputs.iter().zip(offsets.iter()).zip(section_names.iter())
+    {
+        writer.write_section_header(&SectionHeader
+        {
+            name: Some(*name),
+            sh_type: match (output.synthetic, output.nobits)
+            {
+                (Some(SyntheticSection::RelaDyn), _) => elf::SHT_RELA,
+                (Some(SyntheticSection::Dynamic), _) => elf::SHT_DYNAMIC,
+                (_, true) => elf::SHT_NOBITS,
+                (_, false) => elf::SHT_PROGBITS
+            },
+            sh_flags: (elf::SHF_ALLOC | match output.segment
+            {
+                SectionSegment::LoadableRead => 0,
+                SectionSegment::LoadableReadWrite => elf::SHF_WRITE,
+                SectionSegment::LoadableReadExec => elf::SHF_EXECINSTR
+            } | if output.tls { elf::SHF_TLS } else { 0 }) as u64,
+            sh_addr: output.base_virt_addr,
+            sh_offset: *offset as u64,
+            sh_size: output.size,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: output.alignment,
+            sh_entsize: match output.synthetic
+            {
+                Some(SyntheticSection::RelaDyn) => match sections.is_64()
+                {
+                    true => std::mem::size_of::<elf::Rela64<Endianness>>() as u64,
+                    false => std::mem::size_of::<elf::Rela32<Endianness>>() as u64
+                },
+                Some(SyntheticSection::Dynamic) => match sections.is_64()
+                {
+                    true => std::mem::size_of::<elf::Dyn64<Endianness>>() as u64,
+                    false => std::mem::size_of::<elf::Dyn32<Endianness>>() as u64
+                },
+                _ => 0
+            }
+        });
+    }
+    if let (Some(attributes), Some(offset), Some(name)) = (&attributes, attributes_offset, attributes_name)
+    {
+        writer.write_section_header(&SectionHeader
+        {
+            name: Some(name),
+            sh_type: attributes::SHT_RISCV_ATTRIBUTES,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset: offset as u64,
+            sh_size: attributes.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 1,
+            sh_entsize: 0
+        });
+    }
+    for ((output, offset), name) in debug.iter().zip(debug_offsets.iter()).zip(debug_names.iter())
+    {
+        writer.write_section_header(&SectionHeader
+        {
+            name: Some(*name),
+            sh_type: elf::SHT_PROGBITS,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset: *offset as u64,
+            sh_size: output.size,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: output.alignment,
+            sh_entsize: 0
+        });
+    }
+    if has_symtab
+    {
+        writer.write_symtab_section_header(1 + local_count as u32); /* the null symbol is local, too */
+        writer.write_strtab_section_header();
+    }
+    writer.write_shstrtab_section_header();
+
+    output_buffer
+}
@@ -0,0 +1,250 @@
+/* Generate Intel HEX and Motorola S-record images
+ *
+ * Both formats describe memory contents as lines of ASCII hex records, each with
+ * an address, up to 16 bytes of data, and a checksum. Flash programmers and FPGA
+ * soft-core loaders often take only these formats.
+ *
+ * Intel HEX records have 16-bit addresses, so extended linear address records (type 04)
+ * set the upper 16 bits of the addresses that follow. A start linear address record
+ * (type 05) gives the entry point, and an end of file record (type 01) finishes the image.
+ *
+ * S-records use S3 data records and an S7 entry point record, with 32-bit addresses. Loaders
+ * that only take narrower addresses can be given the narrowest records that fit the image and
+ * its entry point instead: S1 and S9 for 16-bit addresses, S2 and S8 for 24-bit, or S3 and S7.
+ * An S0 header record starts the image, and an S5 or S6 record counts the data records.
+ *
+ * Lines end in CR LF, as they do in the files objcopy generates.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See LICENSE for usage and copying.
+ */
+
+/* maximum number of data bytes in a record */
+const RECORD_DATA_SIZE: usize = 16;
+
+/* Intel HEX record types */
+const IHEX_DATA: u8 = 0x00;
+const IHEX_END_OF_FILE: u8 = 0x01;
+const IHEX_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const IHEX_START_LINEAR_ADDRESS: u8 = 0x05;
+
+/* a run of bytes to load at the given physical address */
+pub struct Chunk
+{
+    pub address: u64,
+    pub data: Vec<u8>
+}
+
+/* generate an Intel HEX image of the given chunks, starting execution at the given entry point */
+pub fn to_ihex(chunks: &[Chunk], entry: u64) -> Vec<u8>
+{
+    let mut text = String::new();
+    let mut upper: Option<u64> = None;
+
+    for (address, data) in split_records(chunks, "Intel HEX")
+    {
+        /* records can only address 64KiB at a time, so update the upper 16 bits when they change */
+        if upper != Some(address >> 16)
+        {
+            upper = Some(address >> 16);
+            text.push_str(&ihex_record(0, IHEX_EXTENDED_LINEAR_ADDRESS, &((address >> 16) as u16).to_be_bytes()));
+        }
+        text.push_str(&ihex_record((address & 0xffff) as u16, IHEX_DATA, data));
+    }
+
+    text.push_str(&ihex_record(0, IHEX_START_LINEAR_ADDRESS, &(check_address(entry, "Intel HEX") as u32).to_be_bytes()));
+    text.push_str(&ihex_record(0, IHEX_END_OF_FILE, &[]));
+    text.into_bytes()
+}
+
+/* generate a Motorola S-record image of the given chunks, starting execution at the given entry point.
+   if narrow is true, use the narrowest records that hold the highest address rather than S3 and S7 */
+pub fn to_srec(chunks: &[Chunk], entry: u64, narrow: bool) -> Vec<u8>
+{
+    let records = split_records(chunks, "S-record");
+    let mut text = srec_record('0', &[0, 0], &[]);
+    let mut count = 0;
+
+    /* if asked to, pick the data and entry point record types by the highest address they need to hold */
+    let highest = records.iter()
+        .map(|(address, data)| address + data.len() as u64 - 1)
+        .chain(std::iter::once(check_address(entry, "S-record")))
+        .max()
+        .unwrap_or(0);
+    let (data_type, entry_type, width) = match highest
+    {
+        0..=0xffff if narrow => ('1', '9', 2),
+        0x10000..=0xffffff if narrow => ('2', '8', 3),
+        _ => ('3', '7', 4)
+    };
+
+    for (address, data) in records
+    {
+        text.push_str(&srec_record(data_type, &(address as u32).to_be_bytes()[4 - width..], data));
+        count += 1;
+    }
+
+    /* count the data records with a 16-bit S5 or, if there are too many, a 24-bit S6 record */
+    match count
+    {
+        0..=0xffff => text.push_str(&srec_record('5', &(count as u16).to_be_bytes(), &[])),
+        0x10000..=0xffffff => text.push_str(&srec_record('6', &(count as u32).to_be_bytes()[1..], &[])),
+        _ => ()
+    }
+
+    text.push_str(&srec_record(entry_type, &(entry as u32).to_be_bytes()[4 - width..], &[]));
+    text.into_bytes()
+}
+
+/* split the chunks into records of up to 16 bytes that don't cross a 64KiB boundary,
+   returning each record's address and data */
+fn split_records<'a>(chunks: &'a [Chunk], format: &str) -> Vec<(u64, &'a [u8])>
+{
+    let mut records = Vec::new();
+
+    for chunk in chunks
+    {
+        let mut offset = 0;
+        while offset < chunk.data.len()
+        {
+            let address = chunk.address + offset as u64;
+            let to_boundary = (0x10000 - (address & 0xffff)) as usize;
+            let length = RECORD_DATA_SIZE.min(chunk.data.len() - offset).min(to_boundary);
+
+            check_address(address + length as u64 - 1, format);
+            records.push((address, &chunk.data[offset..offset + length]));
+            offset += length;
+        }
+    }
+
+    records
+}
+
+/* bail out if the given address can't be expressed in 32 bits */
+fn check_address(address: u64, format: &str) -> u64
+{
+    if address > u32::MAX as u64
+    {
+        fatal_msg!("Can't generate {} image: address 0x{:x} doesn't fit in 32 bits", format, address);
+    }
+    address
+}
+
+/* generate an Intel HEX record: byte count, 16-bit address, type, data, and the two's complement
+   of the sum of those bytes, eg: :0400000501020304ED */
+fn ihex_record(address: u16, record_type: u8, data: &[u8]) -> String
+{
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend_from_slice(data);
+
+    let checksum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+    format!(":{}{:02X}\r\n", to_hex(&bytes), checksum)
+}
+
+/* generate an S-record: type, byte count, address, data, and the ones' complement
+   of the sum of the count, address, and data bytes, eg: S30800001000010203E1 */
+fn srec_record(record_type: char, address: &[u8], data: &[u8]) -> String
+{
+    let mut bytes = vec![(address.len() + data.len() + 1) as u8];
+    bytes.extend_from_slice(address);
+    bytes.extend_from_slice(data);
+
+    let checksum = !bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    format!("S{}{}{:02X}\r\n", record_type, to_hex(&bytes), checksum)
+}
+
+fn to_hex(bytes: &[u8]) -> String
+{
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn lines(image: Vec<u8>) -> Vec<String>
+    {
+        String::from_utf8(image).unwrap().split_terminator("\r\n").map(String::from).collect()
+    }
+
+    #[test]
+    fn record_checksums()
+    {
+        assert_eq!(ihex_record(0, IHEX_START_LINEAR_ADDRESS, &[ 1, 2, 3, 4 ]), ":0400000501020304ED\r\n");
+        assert_eq!(ihex_record(0x0100, IHEX_DATA, &[ 0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01,
+                                                     0x36, 0x00, 0x7e, 0xfe, 0x09, 0xd2, 0x19, 0x01 ]),
+                   ":10010000214601360121470136007EFE09D2190140\r\n");
+        assert_eq!(ihex_record(0, IHEX_END_OF_FILE, &[]), ":00000001FF\r\n");
+
+        assert_eq!(srec_record('3', &[ 0, 0, 0x10, 0 ], &[ 1, 2, 3 ]), "S30800001000010203E1\r\n");
+        assert_eq!(srec_record('1', &[ 0, 0 ], &[ 0x28, 0x5f, 0x24, 0x5f, 0x22, 0x12, 0x22, 0x6a,
+                                                  0x00, 0x04, 0x24, 0x29, 0x00, 0x08, 0x23, 0x7c ]),
+                   "S1130000285F245F2212226A000424290008237C2A\r\n");
+        assert_eq!(srec_record('0', &[ 0, 0 ], &[]), "S0030000FC\r\n");
+    }
+
+    #[test]
+    fn ihex_splits_at_64kib()
+    {
+        /* 24 bytes straddling a 64KiB boundary become two records, each preceded by its upper address */
+        let chunks = [ Chunk { address: 0x8000fff8, data: (0..24).collect() } ];
+        let image = lines(to_ihex(&chunks, 0x80000000));
+
+        assert_eq!(image.len(), 6);
+        assert_eq!(image[0], ":0200000480007A");
+        assert!(image[1].starts_with(":08FFF800"));
+        assert_eq!(image[2], ":02000004800179");
+        assert!(image[3].starts_with(":10000000"));
+        assert_eq!(image[4], ":040000058000000077");
+        assert_eq!(image[5], ":00000001FF");
+
+        /* records stop at the end of a chunk, and are at most 16 bytes long */
+        let chunks = [ Chunk { address: 0x100, data: vec![ 0; 20 ] }, Chunk { address: 0x200, data: vec![ 0; 1 ] } ];
+        let image = lines(to_ihex(&chunks, 0));
+        assert_eq!(image.iter().filter(|line| line.starts_with(":10")).count(), 1);
+        assert!(image.iter().any(|line| line.starts_with(":04011000")));
+        assert!(image.iter().any(|line| line.starts_with(":01020000")));
+    }
+
+    #[test]
+    fn srec_address_width()
+    {
+        /* S3 data records and an S7 entry point record are used whatever the addresses */
+        let image = lines(to_srec(&[ Chunk { address: 0x100, data: vec![ 0xaa ] } ], 0x100, false));
+        assert_eq!(image, vec![ "S0030000FC", "S30600000100AA4E", "S5030001FB", "S70500000100F9" ]);
+
+        /* narrow record types follow the highest address in the image or its entry point */
+        let image = lines(to_srec(&[ Chunk { address: 0x100, data: vec![ 0xaa ] } ], 0x100, true));
+        assert_eq!(image, vec![ "S0030000FC", "S1040100AA50", "S5030001FB", "S9030100FB" ]);
+
+        let image = lines(to_srec(&[ Chunk { address: 0xfffff, data: vec![ 0xaa ] } ], 0x100, true));
+        assert!(image[1].starts_with("S2050FFFFFAA"));
+        assert!(image[3].starts_with("S804000100"));
+
+        let image = lines(to_srec(&[ Chunk { address: 0x100, data: vec![ 0xaa ] } ], 0x80000000, true));
+        assert!(image[1].starts_with("S30600000100AA"));
+        assert!(image[3].starts_with("S70580000000"));
+
+        /* a record's last byte counts, not just its first */
+        let image = lines(to_srec(&[ Chunk { address: 0xfff0, data: vec![ 0; 32 ] } ], 0, true));
+        assert!(image[1].starts_with("S2"));
+    }
+
+    #[test]
+    fn srec_start_and_termination()
+    {
+        /* an empty image still has a header, a count of zero, and its entry point */
+        let image = lines(to_srec(&[], 0x1234, false));
+        assert_eq!(image, vec![ "S0030000FC", "S5030000FC", "S70500001234B4" ]);
+
+        /* the count record tallies the data records */
+        let image = lines(to_srec(&[ Chunk { address: 0, data: vec![ 0; 40 ] } ], 0, false));
+        assert_eq!(image.len(), 6);
+        assert_eq!(image[4], "S5030003F9");
+        assert_eq!(image[5], "S70500000000FA");
+    }
+}
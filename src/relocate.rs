@@ -0,0 +1,1704 @@
+/* Apply RISC-V relocations to input sections, and relax code where possible
+ *
+ * Relaxation shrinks code once the distances between instructions and their
+ * targets are known. It's an iterative process: Collection::arrange() lays out
+ * the sections in memory, relax() looks for instruction sequences that can be
+ * shortened given that layout, and the sections are laid out again with those
+ * bytes deleted until no more can be removed.
+ *
+ * These relaxations are supported for relocations paired with R_RISCV_RELAX:
+ * - AUIPC+JALR calls (R_RISCV_CALL) become JAL, or C.J for tail calls, or C.JAL for
+ *   calls that link to ra in RV32 executables, when in range
+ * - LUI (R_RISCV_HI20) is deleted and the matching LO12 instructions address
+ *   relative to x0 when the target lies within 2KiB of zero, or relative to gp
+ *   when the target lies within 2KiB of __global_pointer$
+ * - AUIPC (R_RISCV_PCREL_HI20) is deleted and the matching LO12 instructions
+ *   address relative to gp when the target lies within 2KiB of __global_pointer$
+ * - loads from the GOT (R_RISCV_GOT_HI20) become AUIPC+ADDI of the target itself
+ *   when it's defined in a section of the executable, so it needs no GOT entry
+ * - the LUI and ADD of local-exec TLS accesses (R_RISCV_TPREL_HI20, R_RISCV_TPREL_ADD)
+ *   are deleted and the LO12 instructions address relative to tp when the variable
+ *   lies within 2KiB of the start of the TLS template
+ *
+ * Initial-exec and general-dynamic TLS accesses (R_RISCV_TLS_GOT_HI20, R_RISCV_TLS_GD_HI20)
+ * are always relaxed to local-exec, as the executable is statically linked and so each
+ * variable's offset from tp is known: the AUIPC becomes a LUI of the offset, the paired
+ * instruction becomes an ADDI, and calls to __tls_get_addr, if it isn't defined, become
+ * add a0, a0, tp.
+ *
+ * Instructions that load gp itself, eg: la gp, __global_pointer$, are never made gp-relative.
+ *
+ * NOPs inserted by the assembler for R_RISCV_ALIGN are always trimmed so that
+ * aligned code stays aligned as the code around it shrinks.
+ *
+ * Debugging information sections are relocated in the same way but never relaxed. References
+ * to them resolve to offsets within their output sections, as they aren't loaded into memory.
+ * References from them to sections left out of the executable resolve to tombstone values.
+ *
+ * (c) Chris Williams, 2021.
+ *
+ * See LICENSE for usage and copying.
+ */
+
+use super::gather::{ Collection, SymbolDefinition, SyntheticSection, EF_RISCV_RVC, RELA_ENTRY_WORDS };
+use super::manifest::{ self, Manifest, FileIdentifier };
+use super::config::Config;
+
+use std::collections::HashMap;
+use indexmap::map::IndexMap;
+use object::elf;
+use object::{ Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget, SectionIndex, SymbolIndex };
+
+/* registers and instructions used when rewriting code */
+const REG_ZERO: u32 = 0;
+const REG_GP: u32 = 3;
+const REG_TP: u32 = 4;
+const OPCODE_JAL: u32 = 0x6f;
+const OPCODE_LOAD: u32 = 0x03;
+const OPCODE_LUI: u32 = 0x37;
+const OPCODE_MASK: u32 = 0x7f;
+const OPCODE_ADDI: u32 = 0x13;      /* addi, with funct3 of zero */
+const OPCODE_FUNCT3_MASK: u32 = 0x707f;
+const INSN_NOP: u32 = 0x00000013;   /* addi x0, x0, 0 */
+const INSN_ADD_A0_TP: u32 = 0x00450533; /* add a0, a0, tp */
+const INSN_C_NOP: u16 = 0x0001;
+const INSN_C_J: u16 = 0xa001;       /* c.j with a zero offset */
+const INSN_C_JAL: u16 = 0x2001;     /* c.jal with a zero offset, RV32 only */
+const REG_RA: u32 = 1;
+
+/* relocations for ULEB128 numbers, eg: in debugging information, which the object crate doesn't define.
+   they come in pairs at the same offset that set the number to the difference between two addresses */
+const R_RISCV_SET_ULEB128: u32 = 60;
+const R_RISCV_SUB_ULEB128: u32 = 61;
+
+/* DTPREL values in debugging information are offsets from the thread's dynamic thread vector pointer,
+   which the RISC-V psABI places 0x800 bytes past the start of the TLS block to make the most of signed offsets */
+const TLS_DTV_OFFSET: u64 = 0x800;
+
+/* debugging information that refers to code or data left out of the executable is given one of these
+   values in place of an address, so that debuggers don't mistake it for code at address zero, which
+   may well exist on bare metal. a start address of -1 in .debug_ranges and .debug_loc lists selects
+   a new base address, so those get -2, as lld does */
+const TOMBSTONE: u64 = u64::MAX;
+const TOMBSTONE_LIST: u64 = u64::MAX - 1;
+
+/* the function general-dynamic TLS accesses call to find a thread-local variable */
+const TLS_GET_ADDR_SYMBOL: &str = "__tls_get_addr";
+
+/* give up if relaxation hasn't settled after this many layouts */
+pub const MAX_RELAXATION_PASSES: usize = 32;
+
+/* a run of bytes deleted from an input section */
+#[derive(Clone, Copy, PartialEq)]
+pub struct Deletion
+{
+    pub offset: u64,
+    pub length: u64
+}
+
+/* describe how a relocation's instructions have been relaxed */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Relaxation
+{
+    CallToJal,      /* AUIPC+JALR replaced with JAL */
+    CallToCj,       /* AUIPC+JALR replaced with C.J */
+    CallToCjal,     /* AUIPC+JALR replaced with C.JAL */
+    HiDeleted,      /* LUI or AUIPC deleted */
+    LoFromZero,     /* LO12 instruction now addresses relative to x0 */
+    LoFromGp,       /* LO12 instruction now addresses relative to gp */
+    LoFromTp,       /* TPREL LO12 instruction now addresses relative to tp */
+    Unrelaxed,      /* relaxed in an earlier pass but out of reach since, so left as it is */
+    Align(u64)      /* this many NOP padding bytes removed */
+}
+
+/* the relaxations made to an input section */
+#[derive(Clone, Default)]
+pub struct SectionRelaxation
+{
+    pub edits: HashMap<u64, Relaxation>,    /* relaxed relocations, by offset within the original section */
+    pub deletions: Vec<Deletion>            /* bytes removed, in order of offset within the original section */
+}
+
+impl SectionRelaxation
+{
+    /* convert an offset within the original input section to an offset within the relaxed section */
+    pub fn adjust(&self, offset: u64) -> u64
+    {
+        let removed: u64 = self.deletions.iter()
+            .take_while(|deletion| deletion.offset < offset)
+            .map(|deletion| std::cmp::min(deletion.length, offset - deletion.offset))
+            .sum();
+
+        offset - removed
+    }
+
+    /* return the total number of bytes deleted from the section */
+    pub fn deleted(&self) -> u64
+    {
+        self.deletions.iter().map(|deletion| deletion.length).sum()
+    }
+
+    fn delete(&mut self, offset: u64, length: u64)
+    {
+        if length > 0
+        {
+            self.deletions.push(Deletion { offset, length });
+        }
+    }
+}
+
+/* a RISC-V relocation within an input section */
+struct Reloc
+{
+    offset: u64,
+    r_type: u32,
+    target: RelocationTarget,
+    addend: i64,
+    relax: bool     /* true if paired with R_RISCV_RELAX */
+}
+
+/* read an input section's relocations, noting which can be relaxed */
+fn read_relocations(section: &object::Section) -> Vec<Reloc>
+{
+    let mut relocs: Vec<Reloc> = Vec::new();
+
+    for (offset, reloc) in section.relocations()
+    {
+        let r_type = match (reloc.kind(), reloc.size())
+        {
+            (RelocationKind::Absolute, 32) => elf::R_RISCV_32,
+            (RelocationKind::Absolute, 64) => elf::R_RISCV_64,
+            (RelocationKind::Elf(r_type), _) => r_type,
+            (kind, size) => fatal_msg!("Unexpected error: unrecognized {}-bit relocation {:?}", size, kind)
+        };
+
+        /* R_RISCV_RELAX marks the relocation before it at the same offset as relaxable */
+        if r_type == elf::R_RISCV_RELAX
+        {
+            if let Some(previous) = relocs.last_mut()
+            {
+                if previous.offset == offset
+                {
+                    previous.relax = true;
+                }
+            }
+            continue;
+        }
+
+        relocs.push(Reloc { offset, r_type, target: reloc.target(), addend: reloc.addend(), relax: false });
+    }
+
+    relocs
+}
+
+/* find the address of a relocation's target. undefined weak symbols are treated as zero */
+fn target_address(collection: &Collection, identifier: &FileIdentifier, parsed: &object::File, target: RelocationTarget) -> u64
+{
+    let symbol = match target
+    {
+        RelocationTarget::Absolute => return 0,
+        RelocationTarget::Section(index) => match collection.section_address(identifier, index, 0)
+        {
+            Some(address) => return address,
+            None => fatal_msg!("Relocation in {} refers to section {} that isn't included in the executable",
+                    identifier.to_str().unwrap(), index.0)
+        },
+        RelocationTarget::Symbol(index) => match parsed.symbol_by_index(index)
+        {
+            Ok(symbol) => symbol,
+            Err(reason) => fatal_msg!("Can't find symbol {} in {}: {}", index.0, identifier.to_str().unwrap(), reason)
+        },
+        other => fatal_msg!("Unexpected error: unrecognized relocation target {:?} in {}", other, identifier.to_str().unwrap())
+    };
+
+    let name = match symbol.is_undefined()
+    {
+        true => collection.reference_name(symbol.name().unwrap_or("")),
+        false => String::from(symbol.name().unwrap_or(""))
+    };
+
+    /* local symbols are resolved within their object file, and globals are looked up
+       in the global symbol table in case they're defined elsewhere. undefined references
+       may be redirected by --wrap */
+    match symbol.section()
+    {
+        object::SymbolSection::Absolute => symbol.address(),
+        object::SymbolSection::Section(index) if symbol.is_local() || symbol.kind() == object::SymbolKind::Section =>
+            match collection.section_address(identifier, index, symbol.address())
+            {
+                Some(address) => address,
+                None => fatal_msg!("Relocation in {} refers to symbol {} in a section that isn't included in the executable",
+                        identifier.to_str().unwrap(), name)
+            },
+        _ => match collection.symbol_address(&name)
+        {
+            Some(address) => address,
+            None if symbol.is_weak() => 0,
+            None => fatal_msg!("Undefined symbol {} referenced in {}", name, identifier.to_str().unwrap())
+        }
+    }
+}
+
+/* return true if a relocation's target is defined in one of its object file's sections that's been left
+   out of the executable, eg: by an exclude pattern, and isn't defined elsewhere */
+fn discarded_target(collection: &Collection, identifier: &FileIdentifier, parsed: &object::File, target: RelocationTarget) -> bool
+{
+    let index = match target
+    {
+        RelocationTarget::Section(index) => index,
+        RelocationTarget::Symbol(index) => match parsed.symbol_by_index(index)
+        {
+            Ok(symbol) if symbol.is_global() && collection.symbol_address(symbol.name().unwrap_or("")).is_some() => return false,
+            Ok(symbol) => match symbol.section()
+            {
+                object::SymbolSection::Section(index) => index,
+                _ => return false
+            },
+            Err(_) => return false
+        },
+        _ => return false
+    };
+
+    collection.section_address(identifier, index, 0).is_none()
+}
+
+/* find the offset of the label a PCREL_LO12 relocation uses to point at its AUIPC */
+fn label_offset(identifier: &FileIdentifier, parsed: &object::File, reloc: &Reloc) -> u64
+{
+    match reloc.target
+    {
+        RelocationTarget::Symbol(index) => match parsed.symbol_by_index(index)
+        {
+            Ok(symbol) => symbol.address(),
+            Err(reason) => fatal_msg!("Can't find symbol {} in {}: {}", index.0, identifier.to_str().unwrap(), reason)
+        },
+        _ => fatal_msg!("PCREL_LO12 relocation in {} at offset 0x{:x} must refer to a label", identifier.to_str().unwrap(), reloc.offset)
+    }
+}
+
+/* find the relocation for the AUIPC instruction at the given offset */
+fn find_hi20<'a>(relocs: &'a [Reloc], identifier: &FileIdentifier, offset: u64) -> &'a Reloc
+{
+    match relocs.iter().find(|reloc| reloc.offset == offset &&
+        matches!(reloc.r_type, elf::R_RISCV_PCREL_HI20 | elf::R_RISCV_GOT_HI20 | elf::R_RISCV_TLS_GOT_HI20 | elf::R_RISCV_TLS_GD_HI20))
+    {
+        Some(reloc) => reloc,
+        None => fatal_msg!("Can't find AUIPC relocation at offset 0x{:x} in {} for PCREL_LO12 relocation",
+                offset, identifier.to_str().unwrap())
+    }
+}
+
+/* describe what an AUIPC is pointed at by its relocation */
+enum Hi20Target
+{
+    Address(u64),   /* the target itself, for PCREL_HI20, or the target's GOT entry, for GOT_HI20 */
+    Direct(u64),    /* the target itself, for a GOT_HI20 relaxed to address the target directly */
+    TpOffset(u64)   /* the thread-local target's offset from tp, for a TLS access relaxed to local-exec */
+}
+
+/* work out what the given AUIPC relocation points its instruction at */
+fn hi20_target(collection: &Collection, config: &Config, identifier: &FileIdentifier, parsed: &object::File, hi20: &Reloc) -> Hi20Target
+{
+    let target = || target_address(collection, identifier, parsed, hi20.target).wrapping_add(hi20.addend as u64);
+
+    match hi20.r_type
+    {
+        elf::R_RISCV_GOT_HI20 => match got_entry(collection, config, identifier, parsed, hi20)
+        {
+            Some((entry, _)) => match collection.get_got().get_index_of(&entry)
+            {
+                Some(slot) => Hi20Target::Address(collection.got_address(slot)),
+                None => fatal_msg!("Unexpected error: Can't find GOT entry for relocation at offset 0x{:x} in {}",
+                        hi20.offset, identifier.to_str().unwrap())
+            },
+            None => Hi20Target::Direct(target())
+        },
+        elf::R_RISCV_TLS_GOT_HI20 | elf::R_RISCV_TLS_GD_HI20 => Hi20Target::TpOffset(tp_offset(collection, target(), identifier)),
+        _ => Hi20Target::Address(target())
+    }
+}
+
+/* turn the instruction paired with a relaxed GOT or TLS access into an ADDI. it must be a load,
+   for GOT and initial-exec TLS accesses, or already an ADDI, for general-dynamic TLS accesses */
+fn to_addi(insn: u32, r_type: u32, location: &String) -> u32
+{
+    match (r_type, insn & OPCODE_FUNCT3_MASK, insn & OPCODE_MASK)
+    {
+        (elf::R_RISCV_PCREL_LO12_I, OPCODE_ADDI, _) => insn,
+        (elf::R_RISCV_PCREL_LO12_I, _, OPCODE_LOAD) => (insn & !OPCODE_FUNCT3_MASK) | OPCODE_ADDI,
+        _ => fatal_msg!("Can't relax GOT or TLS access at {}: instruction isn't a load or an addition", location)
+    }
+}
+
+/* return the offset of the thread-local variable at the given address from tp */
+fn tp_offset(collection: &Collection, address: u64, identifier: &FileIdentifier) -> u64
+{
+    match collection.tls_offset(address)
+    {
+        Some(offset) => offset,
+        None => fatal_msg!("Thread-local storage relocation in {} but no thread-local sections in the executable",
+                identifier.to_str().unwrap())
+    }
+}
+
+/* convert an offset from the start of a TLS block into an offset from its DTV pointer */
+fn dtprel(offset: u64) -> u64
+{
+    offset.wrapping_sub(TLS_DTV_OFFSET)
+}
+
+/* return true if the relocation is a call to an undefined __tls_get_addr, which is left behind
+   by general-dynamic TLS accesses relaxed to local-exec */
+fn relaxed_tls_call(collection: &Collection, parsed: &object::File, reloc: &Reloc) -> bool
+{
+    if reloc.r_type != elf::R_RISCV_CALL && reloc.r_type != elf::R_RISCV_CALL_PLT
+    {
+        return false;
+    }
+
+    match reloc.target
+    {
+        RelocationTarget::Symbol(index) => match parsed.symbol_by_index(index)
+        {
+            Ok(symbol) => symbol.name() == Ok(TLS_GET_ADDR_SYMBOL) && collection.symbol_address(TLS_GET_ADDR_SYMBOL).is_none(),
+            Err(_) => false
+        },
+        _ => false
+    }
+}
+
+/* describe the symbol held in an entry in the global offset table (GOT) */
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum GotEntry
+{
+    Global(String),                         /* global symbol, by name */
+    Local(FileIdentifier, SymbolIndex)      /* symbol local to an object file */
+}
+
+/* return the GOT entry a GOT_HI20 relocation needs, and whether that entry must be adjusted
+   by a RELATIVE dynamic relocation in a relocatable executable. return None if the code can
+   be relaxed to address the symbol directly instead, which is possible when the symbol is
+   defined in a section of the executable, as it can then be reached PC-relative */
+fn got_entry(collection: &Collection, config: &Config, identifier: &FileIdentifier, parsed: &object::File, reloc: &Reloc) -> Option<(GotEntry, bool)>
+{
+    let (index, symbol) = match reloc.target
+    {
+        RelocationTarget::Symbol(index) => match parsed.symbol_by_index(index)
+        {
+            Ok(symbol) => (index, symbol),
+            Err(reason) => fatal_msg!("Can't find symbol {} in {}: {}", index.0, identifier.to_str().unwrap(), reason)
+        },
+        _ => fatal_msg!("GOT_HI20 relocation in {} at offset 0x{:x} must refer to a symbol", identifier.to_str().unwrap(), reloc.offset)
+    };
+
+    /* symbols defined in a section of the executable move with it. symbols given values by
+       the linker or the config, eg: with --defsym, are absolute, and undefined weak symbols are zero */
+    let (entry, defined_here, relative) = if symbol.is_local()
+    {
+        let in_section = matches!(symbol.section(), object::SymbolSection::Section(_));
+        (GotEntry::Local(identifier.clone(), index), in_section, in_section)
+    }
+    else
+    {
+        let name = match symbol.is_undefined()
+        {
+            true => collection.reference_name(symbol.name().unwrap_or("")),
+            false => String::from(symbol.name().unwrap_or(""))
+        };
+        match collection.get_symbols().get(&name).map(|global| global.definition)
+        {
+            Some(SymbolDefinition::Section(_, _)) => (GotEntry::Global(name), true, true),
+            Some(SymbolDefinition::Absolute(_)) => (GotEntry::Global(name), false, false),
+            None => (GotEntry::Global(name), false, !symbol.is_weak())
+        }
+    };
+
+    match defined_here && config.get_output().relax() && reloc.relax
+    {
+        true => None,
+        false => Some((entry, relative))
+    }
+}
+
+/* gather up the GOT entries needed by the input sections, noting which hold addresses
+   within the executable that must be adjusted if the executable is relocated. this depends
+   on the symbols defined by laying out the executable, so call it again after each layout */
+pub fn build_got(collection: &Collection, config: &Config, manifest: &Manifest) -> IndexMap<GotEntry, bool>
+{
+    let mut got = IndexMap::new();
+
+    for section_idx in 0..collection.input_section_count()
+    {
+        let (identifier, index) = collection.input_section(section_idx);
+        let parsed = match manifest.get(identifier)
+        {
+            Some(mapping) => manifest::parse(mapping),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?} while building GOT", identifier)
+        };
+        let section = match parsed.section_by_index(index)
+        {
+            Ok(section) => section,
+            Err(reason) => fatal_msg!("Unexpected error: Can't find section {} in {:?}: {}", index.0, identifier, reason)
+        };
+
+        for reloc in read_relocations(&section).iter().filter(|reloc| reloc.r_type == elf::R_RISCV_GOT_HI20)
+        {
+            if let Some((entry, relative)) = got_entry(collection, config, identifier, &parsed, reloc)
+            {
+                got.insert(entry, relative);
+            }
+        }
+    }
+
+    got
+}
+
+/* describe an absolute address written into a relocatable executable's loaded sections
+   that must be adjusted by an R_RISCV_RELATIVE dynamic relocation if it's moved */
+pub struct DynamicReloc
+{
+    section_idx: usize,     /* input section holding the address */
+    offset: u64,            /* offset of the address within that input section */
+    target: RelocationTarget,
+    addend: i64
+}
+
+/* return true if a relocation's target is an address within the executable, and so moves with it,
+   rather than an absolute value, such as a symbol given a value by the linker or the config,
+   or an undefined weak symbol */
+fn relative_target(collection: &Collection, parsed: &object::File, target: RelocationTarget) -> bool
+{
+    let symbol = match target
+    {
+        RelocationTarget::Section(_) => return true,
+        RelocationTarget::Symbol(index) => match parsed.symbol_by_index(index)
+        {
+            Ok(symbol) => symbol,
+            Err(_) => return false
+        },
+        _ => return false
+    };
+
+    if symbol.is_local() || symbol.kind() == object::SymbolKind::Section
+    {
+        return matches!(symbol.section(), object::SymbolSection::Section(_));
+    }
+
+    let name = match symbol.is_undefined()
+    {
+        true => collection.reference_name(symbol.name().unwrap_or("")),
+        false => String::from(symbol.name().unwrap_or(""))
+    };
+    match collection.get_symbols().get(&name).map(|global| global.definition)
+    {
+        Some(SymbolDefinition::Section(_, _)) => true,
+        Some(SymbolDefinition::Absolute(_)) => false,
+        None => !symbol.is_weak()
+    }
+}
+
+/* gather up the absolute addresses within a relocatable executable's loaded sections, each of which
+   needs a dynamic relocation. only word-sized addresses can be adjusted by the loader, so smaller
+   references to addresses within the executable can't be relocated and are rejected */
+pub fn build_dynamic_relocs(collection: &Collection, config: &Config, manifest: &Manifest) -> Vec<DynamicReloc>
+{
+    let mut dynamic = Vec::new();
+
+    if !config.get_output().is_relocatable()
+    {
+        return dynamic;
+    }
+
+    let word_type = match collection.is_64()
+    {
+        true => elf::R_RISCV_64,
+        false => elf::R_RISCV_32
+    };
+
+    for section_idx in 0..collection.input_section_count()
+    {
+        let (identifier, index) = collection.input_section(section_idx);
+        let parsed = match manifest.get(identifier)
+        {
+            Some(mapping) => manifest::parse(mapping),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?} while finding dynamic relocations", identifier)
+        };
+        let section = match parsed.section_by_index(index)
+        {
+            Ok(section) => section,
+            Err(reason) => fatal_msg!("Unexpected error: Can't find section {} in {:?}: {}", index.0, identifier, reason)
+        };
+
+        for reloc in read_relocations(&section).iter().filter(|reloc| reloc.r_type == elf::R_RISCV_32 || reloc.r_type == elf::R_RISCV_64)
+        {
+            if !relative_target(collection, &parsed, reloc.target)
+            {
+                continue;
+            }
+
+            if reloc.r_type != word_type
+            {
+                fatal_msg!("Relocation in {} section {} at offset 0x{:x} needs a {}-bit absolute address, which can't be adjusted when \
+                    the executable is relocated. Build with -fPIC or -fPIE, or use a static placement",
+                    identifier.to_str().unwrap(), section.name().unwrap_or("?"), reloc.offset,
+                    match reloc.r_type { elf::R_RISCV_32 => 32, _ => 64 });
+            }
+
+            dynamic.push(DynamicReloc { section_idx, offset: reloc.offset, target: reloc.target, addend: reloc.addend });
+        }
+    }
+
+    dynamic
+}
+
+/* return the contents of a section generated by the linker. call arrange() before this */
+pub fn synthetic_data(collection: &Collection, synthetic: SyntheticSection, manifest: &Manifest) -> Vec<u8>
+{
+    let mut data = Vec::new();
+
+    /* addresses are 64 or 32 bits wide, depending on the executable */
+    let word = |value: u64| value.to_le_bytes()[..collection.address_size() as usize].to_vec();
+
+    /* r_offset, r_info, and r_addend of an Elf64_Rela or Elf32_Rela. RELATIVE
+       relocations have no symbol, so r_info is just the relocation type */
+    let rela = |data: &mut Vec<u8>, location: u64, value: u64|
+    {
+        data.extend(word(location));
+        data.extend(word(elf::R_RISCV_RELATIVE as u64));
+        data.extend(word(value));
+    };
+
+    match synthetic
+    {
+        SyntheticSection::Got => for (entry, relative) in collection.get_got()
+        {
+            data.extend(word(got_value(collection, entry, *relative, manifest)));
+        },
+        SyntheticSection::RelaDyn =>
+        {
+            for (slot, (entry, _)) in collection.get_got().iter().enumerate().filter(|(_, (_, relative))| **relative)
+            {
+                rela(&mut data, collection.got_address(slot), got_value(collection, entry, true, manifest));
+            }
+
+            for reloc in collection.get_dynamic_relocs()
+            {
+                let (identifier, _) = collection.input_section(reloc.section_idx);
+                let value = match manifest.get(identifier)
+                {
+                    Some(mapping) => target_address(collection, identifier, &manifest::parse(mapping), reloc.target),
+                    None => fatal_msg!("Unexpected error: Can't find mapping for file {:?} while writing dynamic relocations", identifier)
+                };
+                rela(&mut data, collection.address_of(reloc.section_idx, reloc.offset), value.wrapping_add(reloc.addend as u64));
+            }
+        },
+        SyntheticSection::Dynamic =>
+        {
+            /* point the loader at the dynamic relocations, if there are any, then end the section */
+            let count = collection.dynamic_reloc_count();
+            if count > 0
+            {
+                let entry_size = RELA_ENTRY_WORDS * collection.address_size();
+                let rela_address = match collection.synthetic_address(SyntheticSection::RelaDyn)
+                {
+                    Some(address) => address,
+                    None => fatal_msg!("Unexpected error: dynamic relocations are missing from the executable")
+                };
+                for (tag, value) in [(elf::DT_RELA, rela_address), (elf::DT_RELASZ, count * entry_size),
+                                     (elf::DT_RELAENT, entry_size), (elf::DT_RELACOUNT, count)]
+                {
+                    data.extend(word(tag as u64));
+                    data.extend(word(value));
+                }
+            }
+            data.extend(word(elf::DT_NULL as u64));
+            data.extend(word(0));
+        },
+        SyntheticSection::Embed(_) => fatal_msg!("Unexpected error: embedded files don't come from the GOT")
+    }
+
+    data
+}
+
+/* return the address held in the given GOT entry */
+fn got_value(collection: &Collection, entry: &GotEntry, relative: bool, manifest: &Manifest) -> u64
+{
+    match entry
+    {
+        GotEntry::Global(name) => match collection.symbol_address(name)
+        {
+            Some(address) => address,
+            None if !relative => 0, /* undefined weak symbol */
+            None => fatal_msg!("Undefined symbol {} referenced via the GOT", name)
+        },
+        GotEntry::Local(identifier, index) => match manifest.get(identifier)
+        {
+            Some(mapping) => target_address(collection, identifier, &manifest::parse(mapping), RelocationTarget::Symbol(*index)),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?} while filling GOT", identifier)
+        }
+    }
+}
+
+/* interpret an address, or the difference between two addresses, as a signed value.
+   RV32 addresses wrap around at 4GiB, so only their low 32 bits count */
+fn signed(collection: &Collection, value: u64) -> i64
+{
+    match collection.is_64()
+    {
+        true => value as i64,
+        false => value as u32 as i32 as i64
+    }
+}
+
+/* return true if the value fits in a signed immediate of the given number of bits */
+fn fits(value: i64, bits: u32) -> bool
+{
+    value >= -(1 << (bits - 1)) && value < (1 << (bits - 1))
+}
+
+/* return true if the given U-type or I-type instruction writes to gp */
+fn writes_gp(insn: u32) -> bool
+{
+    (insn >> 7) & 0x1f == REG_GP
+}
+
+/* given the current layout, work out how each input section can be relaxed.
+   relaxations that removed bytes in earlier passes are kept while they still hold,
+   and alignment padding is worked out afresh each time */
+pub fn relax(collection: &Collection, config: &Config, manifest: &Manifest) -> Vec<SectionRelaxation>
+{
+    let enabled = config.get_output().relax();
+    let gp = collection.symbol_address("__global_pointer$");
+    let mut relaxations = Vec::new();
+
+    for section_idx in 0..collection.input_section_count()
+    {
+        let (identifier, index) = collection.input_section(section_idx);
+        let previous = collection.get_relaxation(section_idx);
+        let parsed = match manifest.get(identifier)
+        {
+            Some(mapping) => manifest::parse(mapping),
+            None => fatal_msg!("Unexpected error: Can't find mapping for file {:?} during relaxation", identifier)
+        };
+        let section = match parsed.section_by_index(index)
+        {
+            Ok(section) => section,
+            Err(reason) => fatal_msg!("Unexpected error: Can't find section {} in {:?}: {}", index.0, identifier, reason)
+        };
+        let data = section.data().unwrap_or(&[]);
+        let rvc = match parsed.flags()
+        {
+            object::FileFlags::Elf { e_flags } => e_flags & EF_RISCV_RVC != 0,
+            _ => false
+        };
+
+        let relocs = read_relocations(&section);
+        let mut relaxation = SectionRelaxation::default();
+
+        for reloc in &relocs
+        {
+            let offset = reloc.offset;
+
+            /* trim alignment padding to what's needed at this point in the section,
+               taking into account bytes already deleted earlier in the section */
+            if reloc.r_type == elf::R_RISCV_ALIGN
+            {
+                let padding = reloc.addend as u64;
+                let address = collection.address_of(section_idx, 0) + relaxation.adjust(offset);
+                let needed = match align_padding(address, padding)
+                {
+                    Some(needed) => needed,
+                    None => fatal_msg!("Can't align code in {} at offset 0x{:x}: not enough padding", identifier.to_str().unwrap(), offset)
+                };
+                relaxation.edits.insert(offset, Relaxation::Align(padding - needed));
+                relaxation.delete(offset + needed, padding - needed);
+                continue;
+            }
+
+            if !enabled || !reloc.relax
+            {
+                continue;
+            }
+
+            if relaxed_tls_call(collection, &parsed, reloc)
+            {
+                continue;
+            }
+
+            let symbol = target_address(collection, identifier, &parsed, reloc.target).wrapping_add(reloc.addend as u64);
+            let pc = collection.address_of(section_idx, offset);
+
+            /* list the ways this relocation can be relaxed at the current layout, best first */
+            let candidates = match reloc.r_type
+            {
+                elf::R_RISCV_CALL | elf::R_RISCV_CALL_PLT =>
+                {
+                    let distance = signed(collection, symbol.wrapping_sub(pc));
+                    let rd = (read_u32(data, offset + 4) >> 7) & 0x1f;
+                    let mut candidates = Vec::new();
+                    if rvc && rd == REG_ZERO && fits(distance, 12)
+                    {
+                        candidates.push(Relaxation::CallToCj);
+                    }
+                    if rvc && rd == REG_RA && !collection.is_64() && fits(distance, 12)
+                    {
+                        candidates.push(Relaxation::CallToCjal);
+                    }
+                    if fits(distance, 21)
+                    {
+                        candidates.push(Relaxation::CallToJal);
+                    }
+                    candidates
+                },
+
+                elf::R_RISCV_HI20 | elf::R_RISCV_LO12_I | elf::R_RISCV_LO12_S =>
+                {
+                    let mut bases = Vec::new();
+                    if fits(signed(collection, symbol), 12)
+                    {
+                        bases.push(Relaxation::LoFromZero);
+                    }
+
+                    /* don't make the code that sets up gp depend on gp */
+                    let sets_gp = reloc.r_type != elf::R_RISCV_LO12_S && writes_gp(read_u32(data, offset));
+                    match gp
+                    {
+                        Some(gp) if !sets_gp && fits(signed(collection, symbol.wrapping_sub(gp)), 12) => bases.push(Relaxation::LoFromGp),
+                        _ => ()
+                    }
+
+                    match (bases.is_empty(), reloc.r_type)
+                    {
+                        (false, elf::R_RISCV_HI20) => vec![ Relaxation::HiDeleted ],
+                        (true, elf::R_RISCV_HI20) => Vec::new(),
+                        _ => bases
+                    }
+                },
+
+                elf::R_RISCV_PCREL_HI20 => match gp
+                {
+                    Some(gp) if !writes_gp(read_u32(data, offset)) && fits(signed(collection, symbol.wrapping_sub(gp)), 12) =>
+                        vec![ Relaxation::HiDeleted ],
+                    _ => Vec::new()
+                },
+
+                elf::R_RISCV_TPREL_HI20 | elf::R_RISCV_TPREL_ADD | elf::R_RISCV_TPREL_LO12_I | elf::R_RISCV_TPREL_LO12_S
+                    if fits(signed(collection, tp_offset(collection, symbol, identifier)), 12) =>
+                {
+                    match reloc.r_type
+                    {
+                        elf::R_RISCV_TPREL_HI20 | elf::R_RISCV_TPREL_ADD => vec![ Relaxation::HiDeleted ],
+                        _ => vec![ Relaxation::LoFromTp ]
+                    }
+                },
+
+                elf::R_RISCV_PCREL_LO12_I | elf::R_RISCV_PCREL_LO12_S =>
+                {
+                    /* the target is the one given to the AUIPC this instruction pairs with.
+                       loads from the GOT aren't made gp-relative */
+                    let hi20 = find_hi20(&relocs, identifier, label_offset(identifier, &parsed, reloc));
+                    match (gp, hi20.r_type)
+                    {
+                        (Some(gp), elf::R_RISCV_PCREL_HI20) =>
+                        {
+                            let target = target_address(collection, identifier, &parsed, hi20.target).wrapping_add(hi20.addend as u64);
+                            let sets_gp = writes_gp(read_u32(data, hi20.offset)) ||
+                                (reloc.r_type == elf::R_RISCV_PCREL_LO12_I && writes_gp(read_u32(data, offset)));
+                            match hi20.relax && !sets_gp && fits(signed(collection, target.wrapping_sub(gp)), 12)
+                            {
+                                true => vec![ Relaxation::LoFromGp ],
+                                false => Vec::new()
+                            }
+                        },
+                        _ => Vec::new()
+                    }
+                },
+
+                _ => Vec::new()
+            };
+
+            /* keep a relaxation that removed bytes in an earlier pass while it still holds, so that the layout
+               only shrinks. should alignment padding since have grown and pushed its target out of reach,
+               undo it, and don't try again, or the layout may never settle. the other edits remove nothing
+               and are chosen afresh each time */
+            let edit = match previous.edits.get(&offset)
+            {
+                Some(previous) if candidates.contains(previous) => Some(*previous),
+                Some(Relaxation::CallToJal | Relaxation::CallToCj | Relaxation::CallToCjal | Relaxation::HiDeleted | Relaxation::Unrelaxed) =>
+                    Some(Relaxation::Unrelaxed),
+                _ => candidates.first().copied()
+            };
+
+            match edit
+            {
+                Some(Relaxation::CallToJal) => relaxation.delete(offset + 4, 4),
+                Some(Relaxation::CallToCj | Relaxation::CallToCjal) => relaxation.delete(offset + 2, 6),
+                Some(Relaxation::HiDeleted) => relaxation.delete(offset, 4),
+                _ => ()
+            }
+            if let Some(edit) = edit
+            {
+                relaxation.edits.insert(offset, edit);
+            }
+        }
+
+        relaxation.deletions.sort_by_key(|deletion| deletion.offset);
+        relaxations.push(relaxation);
+    }
+
+    relaxations
+}
+
+/* return the contents of the given input section with its relocations applied and
+   any relaxed bytes removed. call this once the collection has been arranged */
+pub fn section_data(collection: &Collection, section_idx: usize, config: &Config, manifest: &Manifest) -> Vec<u8>
+{
+    relocated_data(collection, collection.input_section(section_idx), collection.get_relaxation(section_idx),
+        &|offset| collection.address_of(section_idx, offset), false, config, manifest)
+}
+
+/* return the contents of an object file's debugging information section with its relocations applied,
+   given the section's offset within its output section. debugging information isn't relaxed, and its
+   references to sections left out of the executable are given tombstone values */
+pub fn debug_section_data(collection: &Collection, identifier: &FileIdentifier, index: SectionIndex, base: u64,
+    config: &Config, manifest: &Manifest) -> Vec<u8>
+{
+    relocated_data(collection, (identifier, index), &SectionRelaxation::default(), &|offset| base + offset, true, config, manifest)
+}
+
+/* return the contents of an object file's section, given by the file and section index, with its relocations
+   applied and the given relaxations made, using the given function to find the address of an offset within
+   the section. set debug if the section holds debugging information */
+fn relocated_data(collection: &Collection, (identifier, index): (&FileIdentifier, SectionIndex), relaxation: &SectionRelaxation,
+    address_of: &dyn Fn(u64) -> u64, debug: bool, config: &Config, manifest: &Manifest) -> Vec<u8>
+{
+    let parsed = match manifest.get(identifier)
+    {
+        Some(mapping) => manifest::parse(mapping),
+        None => fatal_msg!("Unexpected error: Can't find mapping for file {:?} during relocation", identifier)
+    };
+    let section = match parsed.section_by_index(index)
+    {
+        Ok(section) => section,
+        Err(reason) => fatal_msg!("Unexpected error: Can't find section {} in {:?}: {}", index.0, identifier, reason)
+    };
+    let mut data = match section.data()
+    {
+        Ok(data) => data.to_vec(),
+        Err(reason) => fatal_msg!("Can't read section {} in {:?}: {}", index.0, identifier, reason)
+    };
+
+    /* uninitialized data has no contents to relocate */
+    if section.kind() == object::SectionKind::UninitializedData || section.kind() == object::SectionKind::UninitializedTls
+    {
+        return data;
+    }
+
+    let gp = collection.symbol_address("__global_pointer$");
+    let relocs = read_relocations(&section);
+    let tombstone = match (debug, section.name())
+    {
+        (false, _) => None,
+        (true, Ok(".debug_ranges")) | (true, Ok(".debug_loc")) => Some(TOMBSTONE_LIST),
+        (true, _) => Some(TOMBSTONE)
+    };
+    let location = |offset: u64| format!("offset 0x{:x} in section {} of {}", offset, section.name().unwrap_or("?"), identifier.to_str().unwrap());
+
+    for reloc in &relocs
+    {
+        let offset = reloc.offset;
+        let edit = relaxation.edits.get(&offset).copied();
+
+        /* S + A and P in the psABI's terms */
+        let value = match reloc.r_type
+        {
+            elf::R_RISCV_NONE | elf::R_RISCV_ALIGN => 0,
+            _ if relaxed_tls_call(collection, &parsed, reloc) => 0,
+            _ => match tombstone
+            {
+                /* ADD and SUB pairs measure distances within the left-out section, so keep those as they are */
+                Some(tombstone) if discarded_target(collection, identifier, &parsed, reloc.target) => match reloc.r_type
+                {
+                    elf::R_RISCV_32 | elf::R_RISCV_64 => tombstone,
+                    _ => reloc.addend as u64
+                },
+                _ => target_address(collection, identifier, &parsed, reloc.target).wrapping_add(reloc.addend as u64)
+            }
+        };
+        let pc = address_of(offset);
+
+        match reloc.r_type
+        {
+            elf::R_RISCV_NONE | elf::R_RISCV_ALIGN => (),
+
+            elf::R_RISCV_32 =>
+            {
+                /* the value must fit in 32 bits, either zero or sign extended */
+                if value > u32::MAX as u64 && !fits(value as i64, 32)
+                {
+                    fatal_msg!("Relocation R_RISCV_32 at {} can't hold 0x{:x}", location(offset), value);
+                }
+                write_u32(&mut data, offset, value as u32);
+            },
+            elf::R_RISCV_64 => write_u64(&mut data, offset, value),
+            elf::R_RISCV_32_PCREL =>
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                check_range(distance, 32, "R_RISCV_32_PCREL", &location(offset));
+                write_u32(&mut data, offset, distance as u32);
+            },
+
+            elf::R_RISCV_BRANCH =>
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                check_range(distance, 13, "R_RISCV_BRANCH", &location(offset));
+                let insn = read_u32(&data, offset);
+                write_u32(&mut data, offset, encode_b_imm(insn, distance as u32));
+            },
+            elf::R_RISCV_JAL =>
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                check_range(distance, 21, "R_RISCV_JAL", &location(offset));
+                let insn = read_u32(&data, offset);
+                write_u32(&mut data, offset, encode_j_imm(insn, distance as u32));
+            },
+            elf::R_RISCV_RVC_BRANCH =>
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                check_range(distance, 9, "R_RISCV_RVC_BRANCH", &location(offset));
+                let insn = read_u16(&data, offset);
+                write_u16(&mut data, offset, encode_cb_imm(insn, distance as u32));
+            },
+            elf::R_RISCV_RVC_JUMP =>
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                check_range(distance, 12, "R_RISCV_RVC_JUMP", &location(offset));
+                let insn = read_u16(&data, offset);
+                write_u16(&mut data, offset, encode_cj_imm(insn, distance as u32));
+            },
+
+            elf::R_RISCV_CALL | elf::R_RISCV_CALL_PLT if relaxed_tls_call(collection, &parsed, reloc) =>
+            {
+                /* the relaxed TLS access has loaded the variable's offset from tp into a0 */
+                write_u32(&mut data, offset, INSN_ADD_A0_TP);
+                write_u32(&mut data, offset + 4, INSN_NOP);
+            },
+            elf::R_RISCV_CALL | elf::R_RISCV_CALL_PLT =>
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                match edit
+                {
+                    Some(Relaxation::CallToJal) =>
+                    {
+                        check_range(distance, 21, "relaxed R_RISCV_CALL", &location(offset));
+                        let rd = (read_u32(&data, offset + 4) >> 7) & 0x1f;
+                        write_u32(&mut data, offset, encode_j_imm(OPCODE_JAL | rd << 7, distance as u32));
+                    },
+                    Some(Relaxation::CallToCj) =>
+                    {
+                        check_range(distance, 12, "relaxed R_RISCV_CALL", &location(offset));
+                        write_u16(&mut data, offset, encode_cj_imm(INSN_C_J, distance as u32));
+                    },
+                    Some(Relaxation::CallToCjal) =>
+                    {
+                        check_range(distance, 12, "relaxed R_RISCV_CALL", &location(offset));
+                        write_u16(&mut data, offset, encode_cj_imm(INSN_C_JAL, distance as u32));
+                    },
+                    _ =>
+                    {
+                        check_range(distance.wrapping_add(0x800), 32, "R_RISCV_CALL", &location(offset));
+                        let auipc = read_u32(&data, offset);
+                        let jalr = read_u32(&data, offset + 4);
+                        write_u32(&mut data, offset, encode_u_imm(auipc, distance as u32));
+                        write_u32(&mut data, offset + 4, encode_i_imm(jalr, distance as u32));
+                    }
+                }
+            },
+
+            elf::R_RISCV_HI20 => if edit != Some(Relaxation::HiDeleted)
+            {
+                check_range(signed(collection, value).wrapping_add(0x800), 32, "R_RISCV_HI20", &location(offset));
+                let insn = read_u32(&data, offset);
+                write_u32(&mut data, offset, encode_u_imm(insn, value as u32));
+            },
+            elf::R_RISCV_LO12_I | elf::R_RISCV_LO12_S =>
+            {
+                let insn = read_u32(&data, offset);
+                let (insn, imm) = match edit
+                {
+                    Some(Relaxation::LoFromZero) => (set_rs1(insn, REG_ZERO), value),
+                    Some(Relaxation::LoFromGp) => (set_rs1(insn, REG_GP), value.wrapping_sub(gp.unwrap_or(0))),
+                    _ => (insn, value)
+                };
+                if edit.is_some()
+                {
+                    check_range(signed(collection, imm), 12, "relaxed R_RISCV_LO12", &location(offset));
+                }
+                write_u32(&mut data, offset, match reloc.r_type
+                {
+                    elf::R_RISCV_LO12_I => encode_i_imm(insn, imm as u32),
+                    _ => encode_s_imm(insn, imm as u32)
+                });
+            },
+
+            elf::R_RISCV_PCREL_HI20 => if edit != Some(Relaxation::HiDeleted)
+            {
+                let distance = signed(collection, value.wrapping_sub(pc));
+                check_range(distance.wrapping_add(0x800), 32, "R_RISCV_PCREL_HI20", &location(offset));
+                let insn = read_u32(&data, offset);
+                write_u32(&mut data, offset, encode_u_imm(insn, distance as u32));
+            },
+            elf::R_RISCV_GOT_HI20 | elf::R_RISCV_TLS_GOT_HI20 | elf::R_RISCV_TLS_GD_HI20 =>
+            {
+                let insn = read_u32(&data, offset);
+                match hi20_target(collection, config, identifier, &parsed, reloc)
+                {
+                    Hi20Target::Address(target) | Hi20Target::Direct(target) =>
+                    {
+                        let distance = signed(collection, target.wrapping_sub(pc));
+                        check_range(distance.wrapping_add(0x800), 32, "R_RISCV_GOT_HI20", &location(offset));
+                        write_u32(&mut data, offset, encode_u_imm(insn, distance as u32));
+                    },
+                    Hi20Target::TpOffset(tp_offset) =>
+                    {
+                        check_range(signed(collection, tp_offset).wrapping_add(0x800), 32, "relaxed TLS HI20", &location(offset));
+                        write_u32(&mut data, offset, encode_u_imm((insn & !OPCODE_MASK) | OPCODE_LUI, tp_offset as u32));
+                    }
+                }
+            },
+            elf::R_RISCV_PCREL_LO12_I | elf::R_RISCV_PCREL_LO12_S =>
+            {
+                /* the low 12 bits come from the distance calculated for the paired AUIPC */
+                let hi20 = find_hi20(&relocs, identifier, label_offset(identifier, &parsed, reloc));
+                let hi20_pc = address_of(hi20.offset);
+                let insn = read_u32(&data, offset);
+                let (insn, imm) = match hi20_target(collection, config, identifier, &parsed, hi20)
+                {
+                    Hi20Target::Address(target) => match edit
+                    {
+                        Some(Relaxation::LoFromGp) => (set_rs1(insn, REG_GP), target.wrapping_sub(gp.unwrap_or(0))),
+                        _ => (insn, target.wrapping_sub(hi20_pc))
+                    },
+
+                    /* a load from a GOT entry relaxed to a direct access becomes an addition of
+                       the target's offset, and a relaxed TLS access adds the variable's offset from tp */
+                    Hi20Target::Direct(target) => (to_addi(insn, reloc.r_type, &location(offset)), target.wrapping_sub(hi20_pc)),
+                    Hi20Target::TpOffset(tp_offset) => (to_addi(insn, reloc.r_type, &location(offset)), tp_offset)
+                };
+                if edit.is_some()
+                {
+                    check_range(signed(collection, imm), 12, "relaxed R_RISCV_PCREL_LO12", &location(offset));
+                }
+                write_u32(&mut data, offset, match reloc.r_type
+                {
+                    elf::R_RISCV_PCREL_LO12_I => encode_i_imm(insn, imm as u32),
+                    _ => encode_s_imm(insn, imm as u32)
+                });
+            },
+
+            elf::R_RISCV_TPREL_HI20 => if edit != Some(Relaxation::HiDeleted)
+            {
+                let tp_offset = tp_offset(collection, value, identifier);
+                check_range(signed(collection, tp_offset).wrapping_add(0x800), 32, "R_RISCV_TPREL_HI20", &location(offset));
+                let insn = read_u32(&data, offset);
+                write_u32(&mut data, offset, encode_u_imm(insn, tp_offset as u32));
+            },
+            elf::R_RISCV_TPREL_ADD => (), /* marks the ADD of tp, which can be deleted by relaxation */
+            elf::R_RISCV_TPREL_LO12_I | elf::R_RISCV_TPREL_LO12_S =>
+            {
+                let tp_offset = tp_offset(collection, value, identifier);
+                let insn = read_u32(&data, offset);
+                let insn = match edit
+                {
+                    Some(Relaxation::LoFromTp) =>
+                    {
+                        check_range(signed(collection, tp_offset), 12, "relaxed R_RISCV_TPREL_LO12", &location(offset));
+                        set_rs1(insn, REG_TP)
+                    },
+                    _ => insn
+                };
+                write_u32(&mut data, offset, match reloc.r_type
+                {
+                    elf::R_RISCV_TPREL_LO12_I => encode_i_imm(insn, tp_offset as u32),
+                    _ => encode_s_imm(insn, tp_offset as u32)
+                });
+            },
+            elf::R_RISCV_TLS_DTPREL32 => write_u32(&mut data, offset, dtprel(tp_offset(collection, value, identifier)) as u32),
+            elf::R_RISCV_TLS_DTPREL64 => write_u64(&mut data, offset, dtprel(tp_offset(collection, value, identifier))),
+
+            elf::R_RISCV_RVC_LUI =>
+            {
+                /* c.lui can't load zero, so use c.li rd, 0 instead */
+                let insn = read_u16(&data, offset);
+                let hi20 = signed(collection, value.wrapping_add(0x800)) >> 12;
+                if hi20 == 0
+                {
+                    write_u16(&mut data, offset, (insn & 0x0f80) | 0x4001);
+                }
+                else
+                {
+                    check_range(hi20, 6, "R_RISCV_RVC_LUI", &location(offset));
+                    write_u16(&mut data, offset, (insn & 0xef83) | (((hi20 as u16 >> 5) & 1) << 12) | ((hi20 as u16 & 0x1f) << 2));
+                }
+            },
+
+            elf::R_RISCV_ADD8 => data[offset as usize] = data[offset as usize].wrapping_add(value as u8),
+            elf::R_RISCV_ADD16 =>
+            {
+                let sum = read_u16(&data, offset).wrapping_add(value as u16);
+                write_u16(&mut data, offset, sum);
+            },
+            elf::R_RISCV_ADD32 =>
+            {
+                let sum = read_u32(&data, offset).wrapping_add(value as u32);
+                write_u32(&mut data, offset, sum);
+            },
+            elf::R_RISCV_ADD64 =>
+            {
+                let sum = read_u64(&data, offset).wrapping_add(value);
+                write_u64(&mut data, offset, sum);
+            },
+            elf::R_RISCV_SUB8 => data[offset as usize] = data[offset as usize].wrapping_sub(value as u8),
+            elf::R_RISCV_SUB16 =>
+            {
+                let difference = read_u16(&data, offset).wrapping_sub(value as u16);
+                write_u16(&mut data, offset, difference);
+            },
+            elf::R_RISCV_SUB32 =>
+            {
+                let difference = read_u32(&data, offset).wrapping_sub(value as u32);
+                write_u32(&mut data, offset, difference);
+            },
+            elf::R_RISCV_SUB64 =>
+            {
+                let difference = read_u64(&data, offset).wrapping_sub(value);
+                write_u64(&mut data, offset, difference);
+            },
+            elf::R_RISCV_SUB6 =>
+            {
+                let byte = data[offset as usize];
+                data[offset as usize] = (byte & 0xc0) | (byte.wrapping_sub(value as u8) & 0x3f);
+            },
+            elf::R_RISCV_SET6 =>
+            {
+                let byte = data[offset as usize];
+                data[offset as usize] = (byte & 0xc0) | (value as u8 & 0x3f);
+            },
+            elf::R_RISCV_SET8 => data[offset as usize] = value as u8,
+            elf::R_RISCV_SET16 => write_u16(&mut data, offset, value as u16),
+            elf::R_RISCV_SET32 => write_u32(&mut data, offset, value as u32),
+            R_RISCV_SET_ULEB128 =>
+            {
+                /* the end address alone may not fit in the number, so apply the paired R_RISCV_SUB_ULEB128 now */
+                let start = relocs.iter()
+                    .find(|other| other.offset == offset && other.r_type == R_RISCV_SUB_ULEB128)
+                    .map(|sub| target_address(collection, identifier, &parsed, sub.target).wrapping_add(sub.addend as u64));
+                write_uleb128(&mut data, offset, uleb128_pair(value, start), &location(offset));
+            },
+            R_RISCV_SUB_ULEB128 => if !relocs.iter().any(|other| other.offset == offset && other.r_type == R_RISCV_SET_ULEB128)
+            {
+                let difference = read_uleb128(&data, offset).wrapping_sub(value);
+                write_uleb128(&mut data, offset, difference, &location(offset));
+            },
+
+            other => fatal_msg!("Unsupported relocation type {} at {}", other, location(offset))
+        }
+    }
+
+    /* rewrite the alignment padding that's left as NOPs, and remove deleted bytes */
+    for (offset, edit) in &relaxation.edits
+    {
+        if let Relaxation::Align(removed) = edit
+        {
+            let reloc = relocs.iter().find(|reloc| reloc.offset == *offset && reloc.r_type == elf::R_RISCV_ALIGN).unwrap();
+            write_nops(&mut data, *offset, reloc.addend as u64 - removed);
+        }
+    }
+
+    let mut relaxed = Vec::with_capacity(data.len());
+    let mut next = 0;
+    for deletion in &relaxation.deletions
+    {
+        relaxed.extend_from_slice(&data[next as usize..deletion.offset as usize]);
+        next = deletion.offset + deletion.length;
+    }
+    relaxed.extend_from_slice(&data[next as usize..]);
+    relaxed
+}
+
+/* return the length in bytes of the ULEB128 number at the given offset */
+fn uleb128_length(data: &[u8], offset: u64) -> usize
+{
+    data[offset as usize..].iter().position(|byte| byte & 0x80 == 0).map_or(data.len() - offset as usize, |last| last + 1)
+}
+
+fn read_uleb128(data: &[u8], offset: u64) -> u64
+{
+    data[offset as usize..offset as usize + uleb128_length(data, offset)].iter().enumerate()
+        .fold(0, |value, (idx, byte)| value | ((byte & 0x7f) as u64 * 1u64.checked_shl(idx as u32 * 7).unwrap_or(0)))
+}
+
+/* return the value of an R_RISCV_SET_ULEB128 relocation, less the value of its paired R_RISCV_SUB_ULEB128, if any */
+fn uleb128_pair(end: u64, start: Option<u64>) -> u64
+{
+    end.wrapping_sub(start.unwrap_or(0))
+}
+
+/* overwrite the ULEB128 number at the given offset with the given value, keeping the number's length
+   as the assembler reserved it, with continuation bits set in all but its last byte */
+fn write_uleb128(data: &mut [u8], offset: u64, value: u64, location: &String)
+{
+    let length = uleb128_length(data, offset);
+    if length < 10 && value >> (length * 7) != 0
+    {
+        fatal_msg!("Relocation at {} can't fit 0x{:x} in a {}-byte ULEB128 number", location, value, length);
+    }
+
+    for idx in 0..length
+    {
+        let continuation = if idx + 1 < length { 0x80 } else { 0 };
+        data[offset as usize + idx] = (value.checked_shr(idx as u32 * 7).unwrap_or(0) & 0x7f) as u8 | continuation;
+    }
+}
+
+/* return how many bytes of the given R_RISCV_ALIGN padding at the given address are needed to align the
+   code that follows it, or None if there's not enough padding. the assembler pads with the alignment
+   less the size of the smallest instruction, eg: 6 bytes for 8-byte alignment with compressed code */
+fn align_padding(address: u64, padding: u64) -> Option<u64>
+{
+    let alignment = (padding + 2).next_power_of_two();
+    let needed = ((address + alignment - 1) & !(alignment - 1)) - address;
+    if needed > padding { None } else { Some(needed) }
+}
+
+/* bail out if the given value doesn't fit in a signed field of the given number of bits */
+fn check_range(value: i64, bits: u32, r_type: &str, location: &String)
+{
+    if !fits(value, bits)
+    {
+        fatal_msg!("Relocation {} at {} is out of range: 0x{:x} doesn't fit in {} bits", r_type, location, value, bits);
+    }
+}
+
+/* fill the given number of bytes with NOPs, using a compressed NOP for any leftover 2 bytes */
+fn write_nops(data: &mut [u8], offset: u64, length: u64)
+{
+    let mut position = offset;
+    while position + 4 <= offset + length
+    {
+        write_u32(data, position, INSN_NOP);
+        position += 4;
+    }
+    if position + 2 <= offset + length
+    {
+        write_u16(data, position, INSN_C_NOP);
+    }
+}
+
+/* helpers to read and write little-endian values in section data */
+fn read_u16(data: &[u8], offset: u64) -> u16
+{
+    let offset = offset as usize;
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: u64) -> u32
+{
+    let offset = offset as usize;
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn read_u64(data: &[u8], offset: u64) -> u64
+{
+    (read_u32(data, offset) as u64) | (read_u32(data, offset + 4) as u64) << 32
+}
+
+fn write_u16(data: &mut [u8], offset: u64, value: u16)
+{
+    data[offset as usize..offset as usize + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: u64, value: u32)
+{
+    data[offset as usize..offset as usize + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(data: &mut [u8], offset: u64, value: u64)
+{
+    data[offset as usize..offset as usize + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+/* helpers to insert immediate values into instructions.
+   U-type: upper 20 bits, rounded so that a following signed 12-bit value adds up */
+fn encode_u_imm(insn: u32, value: u32) -> u32
+{
+    (insn & 0xfff) | (value.wrapping_add(0x800) & 0xfffff000)
+}
+
+/* I-type: imm[11:0] in bits 31:20 */
+fn encode_i_imm(insn: u32, value: u32) -> u32
+{
+    (insn & 0x000fffff) | ((value & 0xfff) << 20)
+}
+
+/* S-type: imm[11:5] in bits 31:25, imm[4:0] in bits 11:7 */
+fn encode_s_imm(insn: u32, value: u32) -> u32
+{
+    (insn & 0x01fff07f) | (((value >> 5) & 0x7f) << 25) | ((value & 0x1f) << 7)
+}
+
+/* B-type: imm[12|10:5] in bits 31:25, imm[4:1|11] in bits 11:7 */
+fn encode_b_imm(insn: u32, value: u32) -> u32
+{
+    (insn & 0x01fff07f) | (((value >> 12) & 1) << 31) | (((value >> 5) & 0x3f) << 25)
+        | (((value >> 1) & 0xf) << 8) | (((value >> 11) & 1) << 7)
+}
+
+/* J-type: imm[20|10:1|11|19:12] in bits 31:12 */
+fn encode_j_imm(insn: u32, value: u32) -> u32
+{
+    (insn & 0xfff) | (((value >> 20) & 1) << 31) | (((value >> 1) & 0x3ff) << 21)
+        | (((value >> 11) & 1) << 20) | (((value >> 12) & 0xff) << 12)
+}
+
+/* CB-type: imm[8|4:3] in bits 12:10, imm[7:6|2:1|5] in bits 6:2 */
+fn encode_cb_imm(insn: u16, value: u32) -> u16
+{
+    let value = value as u16;
+    (insn & 0xe383) | (((value >> 8) & 1) << 12) | (((value >> 3) & 3) << 10)
+        | (((value >> 6) & 3) << 5) | (((value >> 1) & 3) << 3) | (((value >> 5) & 1) << 2)
+}
+
+/* CJ-type: imm[11|4|9:8|10|6|7|3:1|5] in bits 12:2 */
+fn encode_cj_imm(insn: u16, value: u32) -> u16
+{
+    let value = value as u16;
+    (insn & 0xe003) | (((value >> 11) & 1) << 12) | (((value >> 4) & 1) << 11)
+        | (((value >> 8) & 3) << 9) | (((value >> 10) & 1) << 8) | (((value >> 6) & 1) << 7)
+        | (((value >> 7) & 1) << 6) | (((value >> 1) & 7) << 3) | (((value >> 5) & 1) << 2)
+}
+
+/* replace an instruction's rs1 register, in bits 19:15 */
+fn set_rs1(insn: u32, reg: u32) -> u32
+{
+    (insn & !(0x1f << 15)) | (reg << 15)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::expr::Expression;
+
+    const INSN_LUI_A0: u32 = 0x00000537;       /* lui a0, 0 */
+    const INSN_ADDI_A0_A0: u32 = 0x00050513;   /* addi a0, a0, 0 */
+    const INSN_SW_A1_A0: u32 = 0x00b52023;     /* sw a1, 0(a0) */
+    const INSN_BEQ_A0_A1: u32 = 0x00b50063;    /* beq a0, a1, 0 */
+    const INSN_JAL_RA: u32 = 0x000000ef;       /* jal ra, 0 */
+    const INSN_C_BEQZ_A0: u16 = 0xc101;        /* c.beqz a0, 0 */
+
+    const INSN_AUIPC_A0: u32 = 0x00000517;     /* auipc a0, 0 */
+    const INSN_LD_A0_A0: u32 = 0x00053503;     /* ld a0, 0(a0) */
+    const INSN_AUIPC_RA: u32 = 0x00000097;     /* auipc ra, 0 */
+    const INSN_JALR_RA: u32 = 0x000080e7;      /* jalr ra, 0(ra) */
+
+    /* describe a section to add to a test object: name, kind, contents, and symbols with their offsets */
+    type TestSection<'a> = (&'a str, object::SectionKind, Vec<u8>, &'a [(&'a str, u64)]);
+
+    /* describe a relocation to add to a test object: section, offset, type, symbol, and addend */
+    type TestReloc<'a> = (object::write::SectionId, u64, u32, &'a str, i64);
+
+    /* generate an RV64 object file with the given sections and relocations. symbols starting .L are local */
+    fn test_object(sections: &[TestSection], relocs: &dyn Fn(&[object::write::SectionId]) -> Vec<TestReloc>) -> Vec<u8>
+    {
+        use object::write::{ Object, Relocation, Symbol, SymbolSection };
+
+        let mut object = Object::new(object::BinaryFormat::Elf, object::Architecture::Riscv64, object::Endianness::Little);
+        let mut ids = Vec::new();
+        let mut symbols = HashMap::new();
+        for (name, kind, data, labels) in sections
+        {
+            let id = object.add_section(Vec::new(), name.as_bytes().to_vec(), *kind);
+            object.append_section_data(id, data, 4);
+            for (label, value) in labels.iter()
+            {
+                symbols.insert(*label, object.add_symbol(Symbol
+                {
+                    name: label.as_bytes().to_vec(),
+                    value: *value,
+                    size: 0,
+                    kind: object::SymbolKind::Label,
+                    scope: if label.starts_with(".L") { object::SymbolScope::Compilation } else { object::SymbolScope::Dynamic },
+                    weak: false,
+                    section: SymbolSection::Section(id),
+                    flags: object::SymbolFlags::None
+                }));
+            }
+            ids.push(id);
+        }
+
+        for (section, offset, r_type, symbol, addend) in relocs(&ids)
+        {
+            object.add_relocation(section, Relocation
+            {
+                offset, size: 0, addend,
+                kind: RelocationKind::Elf(r_type),
+                encoding: object::RelocationEncoding::Generic,
+                symbol: symbols[symbol]
+            }).unwrap();
+        }
+
+        object.write().unwrap()
+    }
+
+    /* arrange the given object file as the given config says, with any symbols defined as --defsym does.
+       the object file is written out under the given name so that it can be mapped into the manifest */
+    fn test_link(name: &str, object: Vec<u8>, config: &str, defsyms: &[(&str, &str)]) -> (Collection, Config, Manifest)
+    {
+        let path = std::env::temp_dir().join(format!("itsylinker-{}-{}.o", name, std::process::id()));
+        std::fs::write(&path, object).unwrap();
+        let mut manifest = Manifest::new();
+        manifest.add(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut config: Config = toml::from_str(config).unwrap();
+        for (name, value) in defsyms
+        {
+            config.assign_symbol(name, Expression::Text(String::from(*value)));
+        }
+
+        let mut collection = Collection::new(&config, &manifest);
+        collection.merge();
+        collection.arrange(&config, &manifest);
+        (collection, config, manifest)
+    }
+
+    /* sign extend the low bits of a value */
+    fn sign_extend(value: u32, bits: u32) -> i64
+    {
+        ((value << (32 - bits)) as i32 >> (32 - bits)) as i64
+    }
+
+    #[test]
+    fn encode_hi20_rounds_for_signed_lo12()
+    {
+        assert_eq!(encode_u_imm(INSN_LUI_A0, 0x12345000), 0x12345537);
+        assert_eq!(encode_u_imm(INSN_LUI_A0, 0x123457ff), 0x12345537);
+
+        /* a low half of 0x800 or more is negative, so the high half is rounded up */
+        assert_eq!(encode_u_imm(INSN_LUI_A0, 0x12345800), 0x12346537);
+        assert_eq!(encode_u_imm(INSN_LUI_A0, 0xfffffffc), 0x00000537);
+
+        /* the pair of immediates always adds back up to the value */
+        for value in [ 0, 1, 0x7ff, 0x800, 0xfff, 0x1000, 0x12345678, 0x7ffff7ff, 0x80000000, 0xfffff800, 0xffffffff ]
+        {
+            let hi = encode_u_imm(INSN_LUI_A0, value) & 0xfffff000;
+            let lo = encode_i_imm(INSN_ADDI_A0_A0, value) >> 20;
+            assert_eq!((hi as i32 as i64 + sign_extend(lo, 12)) as u32, value, "value 0x{:x}", value);
+        }
+    }
+
+    #[test]
+    fn encode_lo12()
+    {
+        assert_eq!(encode_i_imm(INSN_ADDI_A0_A0, -4i32 as u32), 0xffc50513);
+        assert_eq!(encode_i_imm(INSN_ADDI_A0_A0, 0x12345678), 0x67850513);
+        assert_eq!(encode_s_imm(INSN_SW_A1_A0, -4i32 as u32), 0xfeb52e23);
+        assert_eq!(encode_s_imm(INSN_SW_A1_A0, 0x7ff), 0x7eb52fa3);
+
+        /* existing immediates are replaced */
+        assert_eq!(encode_i_imm(0xffc50513, 8), 0x00850513);
+        assert_eq!(encode_s_imm(0xfeb52e23, 0), INSN_SW_A1_A0);
+    }
+
+    #[test]
+    fn encode_branches_and_jumps()
+    {
+        assert_eq!(encode_b_imm(INSN_BEQ_A0_A1, -8i32 as u32), 0xfeb50ce3);
+        assert_eq!(encode_b_imm(INSN_BEQ_A0_A1, 4094), 0x7eb50fe3);
+        assert_eq!(encode_j_imm(INSN_JAL_RA, -2i32 as u32), 0xfffff0ef);
+        assert_eq!(encode_j_imm(INSN_JAL_RA, 0xffffe), 0x7ffff0ef);
+        assert_eq!(encode_cb_imm(INSN_C_BEQZ_A0, -4i32 as u32), 0xdd75);
+        assert_eq!(encode_cb_imm(INSN_C_BEQZ_A0, 254), 0xcd7d);
+        assert_eq!(encode_cj_imm(INSN_C_J, -2048i32 as u32), 0xb001);
+        assert_eq!(encode_cj_imm(INSN_C_J, 2046), 0xaffd);
+        assert_eq!(encode_cj_imm(INSN_C_JAL, 0), INSN_C_JAL);
+    }
+
+    #[test]
+    fn set_rs1_replaces_register()
+    {
+        assert_eq!(set_rs1(INSN_ADDI_A0_A0, REG_GP), 0x00018513);
+        assert_eq!(set_rs1(INSN_ADDI_A0_A0, REG_ZERO), 0x00000513);
+    }
+
+    #[test]
+    fn adjust_offsets_around_deletions()
+    {
+        let mut relaxation = SectionRelaxation::default();
+        relaxation.delete(8, 4);
+        relaxation.delete(20, 2);
+        relaxation.delete(30, 0);
+
+        /* before, inside, and after the first deletion */
+        assert_eq!(relaxation.adjust(4), 4);
+        assert_eq!(relaxation.adjust(8), 8);
+        assert_eq!(relaxation.adjust(10), 8);
+        assert_eq!(relaxation.adjust(12), 8);
+        assert_eq!(relaxation.adjust(16), 12);
+
+        /* inside and after the second */
+        assert_eq!(relaxation.adjust(21), 16);
+        assert_eq!(relaxation.adjust(22), 16);
+        assert_eq!(relaxation.adjust(40), 34);
+
+        /* empty deletions aren't recorded */
+        assert_eq!(relaxation.deletions.len(), 2);
+        assert_eq!(relaxation.deleted(), 6);
+    }
+
+    #[test]
+    fn align_trims_padding()
+    {
+        /* 6 bytes of padding for 8-byte alignment */
+        assert_eq!(align_padding(0x1000, 6), Some(0));
+        assert_eq!(align_padding(0x1002, 6), Some(6));
+        assert_eq!(align_padding(0x1004, 6), Some(4));
+        assert_eq!(align_padding(0x1006, 6), Some(2));
+
+        /* 12 bytes for 16-byte alignment without compressed code */
+        assert_eq!(align_padding(0x1004, 12), Some(12));
+        assert_eq!(align_padding(0x100c, 12), Some(4));
+
+        /* padding can't align an odd address */
+        assert_eq!(align_padding(0x1001, 2), None);
+    }
+
+    #[test]
+    fn align_padding_filled_with_nops()
+    {
+        let mut data = vec![0; 10];
+        write_nops(&mut data, 2, 6);
+        assert_eq!(read_u16(&data, 0), 0);
+        assert_eq!(read_u32(&data, 2), INSN_NOP);
+        assert_eq!(read_u16(&data, 6), INSN_C_NOP);
+        assert_eq!(read_u16(&data, 8), 0);
+    }
+
+    #[test]
+    fn uleb128_read_and_write()
+    {
+        let data = vec![ 0xe5, 0x8e, 0x26, 0xff ];
+        assert_eq!(uleb128_length(&data, 0), 3);
+        assert_eq!(read_uleb128(&data, 0), 624485);
+        assert_eq!(read_uleb128(&[ 0x7f ], 0), 0x7f);
+
+        /* the number keeps its length, padded with continuation bits */
+        let mut data = vec![ 0x80, 0x80, 0x00, 0xff ];
+        write_uleb128(&mut data, 0, 8, &String::new());
+        assert_eq!(data, vec![ 0x88, 0x80, 0x00, 0xff ]);
+        write_uleb128(&mut data, 0, 0x100d0, &String::new());
+        assert_eq!(data, vec![ 0xd0, 0x81, 0x04, 0xff ]);
+        assert_eq!(read_uleb128(&data, 0), 0x100d0);
+    }
+
+    #[test]
+    fn uleb128_set_and_sub_pair()
+    {
+        /* the distance between two high addresses fits in a one-byte number, though the addresses don't */
+        let mut data = vec![ 0x00 ];
+        write_uleb128(&mut data, 0, uleb128_pair(0xffffffff80001234, Some(0xffffffff80001200)), &String::new());
+        assert_eq!(data, vec![ 0x34 ]);
+
+        /* a SET without a SUB stores the address itself */
+        let mut data = vec![ 0x80, 0x80, 0x00 ];
+        write_uleb128(&mut data, 0, uleb128_pair(0x1234, None), &String::new());
+        assert_eq!(read_uleb128(&data, 0), 0x1234);
+    }
+
+    #[test]
+    fn dtprel_biased_by_dtv_offset()
+    {
+        /* variables at the start of the TLS block sit 0x800 bytes before the DTV pointer */
+        assert_eq!(dtprel(0), (-0x800i64) as u64);
+        assert_eq!(dtprel(0x10) as u32, (-0x7f0i32) as u32);
+        assert_eq!(dtprel(0x800), 0);
+        assert_eq!(dtprel(0x1000), 0x800);
+    }
+
+    #[test]
+    fn got_follows_symbols_defined_at_layout()
+    {
+        /* _start loads ptr, defined in the object's data, from the GOT, with relaxation allowed */
+        let code = [ INSN_AUIPC_A0.to_le_bytes(), INSN_LD_A0_A0.to_le_bytes() ].concat();
+        let object = test_object(&[ (".text", object::SectionKind::Text, code, &[ ("_start", 0), (".Lpcrel_hi0", 0) ]),
+                                    (".data", object::SectionKind::Data, vec![ 0; 8 ], &[ ("ptr", 0) ]) ],
+            &|ids| vec![ (ids[0], 0, elf::R_RISCV_GOT_HI20, "ptr", 0), (ids[0], 0, elf::R_RISCV_RELAX, "ptr", 0),
+                         (ids[0], 4, elf::R_RISCV_PCREL_LO12_I, ".Lpcrel_hi0", 0), (ids[0], 4, elf::R_RISCV_RELAX, "ptr", 0) ]);
+        let config = "[output]\nentry = \"_start\"\nrelocatable = true\n\
+                      [section.text]\ninclude = [\".text*\"]\n[section.data]\ninclude = [\".data*\"]\n";
+
+        /* left alone, ptr is within the executable, so the load becomes an ADDI of its address and there's no GOT */
+        let (collection, _, _) = test_link("got-section", object.clone(), config, &[]);
+        assert!(collection.get_got().is_empty());
+        assert_eq!(collection.dynamic_reloc_count(), 0);
+
+        /* given an absolute value with --defsym, ptr can't be reached PC-relative, so it needs a GOT entry,
+           which isn't adjusted when the executable is relocated */
+        let (collection, config, manifest) = test_link("got-defsym", object, config, &[ ("ptr", "0x4000") ]);
+        assert_eq!(collection.symbol_address("ptr"), Some(0x4000));
+        assert_eq!(collection.get_got().len(), 1);
+        assert_eq!(collection.get_got().get(&GotEntry::Global(String::from("ptr"))), Some(&false));
+        assert_eq!(collection.dynamic_reloc_count(), 0);
+        assert_eq!(synthetic_data(&collection, SyntheticSection::Got, &manifest), 0x4000u64.to_le_bytes().to_vec());
+
+        /* and the load reads the GOT entry */
+        let got = collection.got_address(0).wrapping_sub(collection.address_of(0, 0));
+        let text = section_data(&collection, 0, &config, &manifest);
+        assert_eq!(&text[0..4], &encode_u_imm(INSN_AUIPC_A0, got as u32).to_le_bytes());
+        assert_eq!(&text[4..8], &encode_i_imm(INSN_LD_A0_A0, got as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn relaxation_undone_when_padding_grows()
+    {
+        /* _start loads sym, then calls far, which is aligned to 16 bytes almost 1MiB away. sym sits
+           before more padding, 2KiB or so from gp. the 12 bytes of padding before far are trimmed
+           to 4 in the first pass, which brings the call within reach of a JAL, and the second
+           trim brings sym within reach of gp, so both are relaxed in the second pass */
+        let align = 0x10000c;
+        let far = align + 12;
+        let sym = far + 4;
+        let gp = sym + 8 + 12 + 2032;
+        let mut code = INSN_NOP.to_le_bytes().repeat((gp + 4) as usize / 4);
+        for (offset, insn) in [ (0, INSN_LUI_A0), (4, INSN_ADDI_A0_A0), (20, INSN_AUIPC_RA), (24, INSN_JALR_RA) ]
+        {
+            code[offset..offset + 4].copy_from_slice(&insn.to_le_bytes());
+        }
+        let object = test_object(&[ (".text", object::SectionKind::Text, code,
+                                      &[ ("_start", 0), ("far", far), (".Lsym", sym), ("__global_pointer$", gp) ]) ],
+            &|ids| vec![ (ids[0], 0, elf::R_RISCV_HI20, ".Lsym", 0), (ids[0], 0, elf::R_RISCV_RELAX, ".Lsym", 0),
+                         (ids[0], 4, elf::R_RISCV_LO12_I, ".Lsym", 0), (ids[0], 4, elf::R_RISCV_RELAX, ".Lsym", 0),
+                         (ids[0], 20, elf::R_RISCV_CALL, "far", 0), (ids[0], 20, elf::R_RISCV_RELAX, "far", 0),
+                         (ids[0], align, elf::R_RISCV_ALIGN, "_start", 12),
+                         (ids[0], sym + 8, elf::R_RISCV_ALIGN, "_start", 12) ]);
+        let config = "[output]\nentry = \"_start\"\nrelocatable = false\nbase_phys_addr = 0x80000000\n\
+                      [section.text]\ninclude = [\".text*\"]\n";
+        let (collection, config, manifest) = test_link("align-grows", object, config, &[]);
+
+        /* deleting the LUI in front of the call grew the padding by 4 bytes more than was planned for,
+           leaving far out of reach, so the call is put back and the padding grows to make up for it */
+        let relaxation = collection.get_relaxation(0);
+        assert!(relaxation.edits.get(&0) == Some(&Relaxation::HiDeleted));
+        assert!(relaxation.edits.get(&4) == Some(&Relaxation::LoFromGp));
+        assert!(relaxation.edits.get(&20) == Some(&Relaxation::Unrelaxed));
+        assert!(relaxation.edits.get(&align) == Some(&Relaxation::Align(4)));
+        assert_eq!(collection.address_of(0, far) % 16, 0);
+
+        /* and the call reaches far */
+        let text = section_data(&collection, 0, &config, &manifest);
+        let distance = collection.address_of(0, far).wrapping_sub(collection.address_of(0, 20));
+        assert_eq!(&text[16..20], &encode_u_imm(INSN_AUIPC_RA, distance as u32).to_le_bytes());
+        assert_eq!(&text[20..24], &encode_i_imm(INSN_JALR_RA, distance as u32).to_le_bytes());
+    }
+}